@@ -1,19 +1,114 @@
+use cosmic_wanderer_protocol::runtime;
+use freedesktop_desktop_entry::current_desktop;
+use log::warn;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[cfg(feature = "config_file")]
 use {
     config::{Config as ConfigLoader, File},
     dirs::config_dir,
     std::fs,
-    std::path::{Path, PathBuf},
+    std::path::Path,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GeneralConfig {
     pub icon_theme: String,
     pub icon_size: u16,
+    /// Logical sizes rendered in addition to `icon_size`, e.g. for a larger
+    /// grid or list icon in the launcher theme. The launcher asks for sizes
+    /// missing here on every start, so list its `icon_size` to avoid that.
+    #[serde(default)]
+    pub extra_icon_sizes: Vec<u16>,
+    /// Output scales every size is rendered at.
+    #[serde(default = "default_icon_scales")]
+    pub icon_scales: Vec<u16>,
+    /// Read through `GeneralConfig::socket_path`, which replaces an unsafe
+    /// value with the default.
+    #[serde(default = "default_socket_path")]
     pub socket_path: String,
+    /// Where the entry cache read by the launcher is written.
+    #[serde(default = "default_cache_path")]
+    pub cache_path: String,
     pub blacklist: Vec<String>,
+    /// Desktop names matched against `OnlyShowIn`/`NotShowIn`. Empty uses
+    /// `$XDG_CURRENT_DESKTOP`.
+    #[serde(default)]
+    pub current_desktop: Vec<String>,
+    /// How long the watcher waits for filesystem events to stop before rebuilding.
+    #[serde(default = "default_watch_debounce_ms")]
+    pub watch_debounce_ms: u64,
+    /// Worker threads used to rasterise icons; 0 uses one per CPU.
+    #[serde(default)]
+    pub render_threads: usize,
+}
+
+fn default_watch_debounce_ms() -> u64 {
+    250
+}
+
+fn default_socket_path() -> String {
+    runtime::default_socket_path().to_string_lossy().to_string()
+}
+
+fn default_cache_path() -> String {
+    runtime::default_cache_path().to_string_lossy().to_string()
+}
+
+fn default_icon_scales() -> Vec<u16> {
+    vec![1, 2]
+}
+
+impl GeneralConfig {
+    /// Where to listen. A `socket_path` outside a private directory, such as
+    /// the `/tmp` path earlier versions wrote into every config, is ignored
+    /// with a warning rather than refusing to start.
+    pub fn socket_path(&self) -> PathBuf {
+        let (path, rejected) = runtime::resolve_socket_path(&self.socket_path);
+        if let Some(e) = rejected {
+            warn!(
+                "ignoring socket_path {}: {}; using {}",
+                self.socket_path,
+                e,
+                path.display()
+            );
+        }
+        path
+    }
+
+    /// Every (size, scale) pair icons are pre-rendered at, base size first.
+    pub fn icon_variants(&self) -> Vec<(u16, u16)> {
+        let mut sizes = vec![self.icon_size];
+        for size in &self.extra_icon_sizes {
+            if !sizes.contains(size) {
+                sizes.push(*size);
+            }
+        }
+        let mut scales: Vec<u16> = self.icon_scales.iter().map(|s| (*s).max(1)).collect();
+        if scales.is_empty() {
+            scales.push(1);
+        }
+        scales.sort_unstable();
+        scales.dedup();
+
+        sizes
+            .iter()
+            .flat_map(|size| scales.iter().map(move |scale| (*size, *scale)))
+            .collect()
+    }
+
+    /// The configured desktop names, or those from `$XDG_CURRENT_DESKTOP`.
+    pub fn desktop_names(&self) -> Vec<String> {
+        if self.current_desktop.is_empty() {
+            current_desktop().unwrap_or_default()
+        } else {
+            self.current_desktop
+                .iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect()
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,8 +122,14 @@ pub fn default_config() -> Config {
         general: GeneralConfig {
             icon_theme: "Papirus-Dark".to_string(),
             icon_size: 16,
-            socket_path: "/tmp/comsic-wanderer.sock".to_string(),
+            extra_icon_sizes: Vec::new(),
+            icon_scales: default_icon_scales(),
+            socket_path: default_socket_path(),
+            cache_path: default_cache_path(),
             blacklist: Vec::new(),
+            current_desktop: Vec::new(),
+            watch_debounce_ms: default_watch_debounce_ms(),
+            render_threads: 0,
         },
     }
 }
@@ -38,11 +139,18 @@ pub fn default_config() -> Config {
     Config {
         general: GeneralConfig {
             icon_theme: "Papirus-Dark".to_string(),
-            socket_path: "/tmp/comsic-wanderer.sock".to_string(),
+            icon_size: 16,
+            extra_icon_sizes: vec![64],
+            icon_scales: default_icon_scales(),
+            socket_path: default_socket_path(),
+            cache_path: default_cache_path(),
             blacklist: vec!["syncthing-start", "syncthing-ui", "vncviewer"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
+            current_desktop: Vec::new(),
+            watch_debounce_ms: default_watch_debounce_ms(),
+            render_threads: 0,
         },
     }
 }
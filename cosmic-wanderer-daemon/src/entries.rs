@@ -1,21 +1,132 @@
-use freedesktop_desktop_entry::{DesktopEntry, Iter, default_paths, get_languages_from_env};
+use cosmic_wanderer_protocol::EntryAction;
+use cosmic_wanderer_protocol::exec::find_executable;
+use freedesktop_desktop_entry::{DesktopEntry, default_paths, get_languages_from_env};
 use freedesktop_icons::lookup;
 use log::debug;
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FALLBACK_ICON: &str = "application-x-executable";
 
 #[derive(Clone)]
 pub struct NormalDesktopEntry {
     pub app_name: String,
     pub comment: String,
+    pub generic_name: String,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
     pub appid: String,
     pub exec: String,
+    pub terminal: bool,
+    pub working_dir: String,
+    pub prefers_non_default_gpu: bool,
+    pub startup_wm_class: String,
     pub icon: String,
+    /// `Icon=` as written, empty if the entry has none; what `%i` expands to.
+    pub icon_name: String,
+    /// The name `icon` was resolved from: `icon_name`, or the fallback.
+    pub resolved_icon_name: String,
+    pub path: PathBuf,
+    pub actions: Vec<EntryAction>,
+}
+
+/// Why a desktop file was left out of the index.
+#[derive(Debug, Clone)]
+pub enum FilterReason {
+    MissingName,
+    Blacklisted,
+    NoDisplay,
+    Hidden,
+    /// A file with the same desktop file ID earlier in the search path wins.
+    Shadowed(PathBuf),
+    OnlyShowIn(String),
+    NotShowIn(String),
+    TryExecMissing(String),
+    /// Another entry with the same normalized name was kept instead.
+    Duplicate(String),
+}
+
+impl fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterReason::MissingName => write!(f, "has no Name"),
+            FilterReason::Blacklisted => write!(f, "blacklisted in config"),
+            FilterReason::NoDisplay => write!(f, "NoDisplay=true"),
+            FilterReason::Hidden => write!(f, "Hidden=true"),
+            FilterReason::Shadowed(path) => write!(f, "shadowed by {}", path.display()),
+            FilterReason::OnlyShowIn(list) => {
+                write!(f, "OnlyShowIn={} excludes the current desktop", list)
+            }
+            FilterReason::NotShowIn(list) => {
+                write!(f, "NotShowIn={} includes the current desktop", list)
+            }
+            FilterReason::TryExecMissing(exe) => write!(f, "TryExec={} is not installed", exe),
+            FilterReason::Duplicate(appid) => write!(f, "duplicate of {}", appid),
+        }
+    }
+}
+
+/// A desktop file that was not indexed, and why.
+pub struct FilteredDesktopEntry {
+    pub appid: String,
+    pub path: PathBuf,
+    pub reason: FilterReason,
+}
+
+/// The `Hidden`, `OnlyShowIn`, `NotShowIn` and `TryExec` checks from the
+/// Desktop Entry spec. `desktops` holds lowercase `XDG_CURRENT_DESKTOP` names.
+fn spec_filter(entry: &DesktopEntry, desktops: &[String]) -> Option<FilterReason> {
+    let on_current_desktop = |list: &[&str]| {
+        list.iter()
+            .any(|name| desktops.iter().any(|d| d.eq_ignore_ascii_case(name.trim())))
+    };
+
+    if entry.desktop_entry("Hidden") == Some("true") {
+        return Some(FilterReason::Hidden);
+    }
+    if let Some(only) = entry.only_show_in()
+        && !on_current_desktop(&only)
+    {
+        return Some(FilterReason::OnlyShowIn(only.join(";")));
+    }
+    if let Some(not) = entry.not_show_in()
+        && on_current_desktop(&not)
+    {
+        return Some(FilterReason::NotShowIn(not.join(";")));
+    }
+    if let Some(try_exec) = entry.desktop_entry("TryExec")
+        && !try_exec.is_empty()
+        && !find_executable(try_exec)
+    {
+        return Some(FilterReason::TryExecMissing(try_exec.to_string()));
+    }
+    None
+}
+
+/// Resolves an `Icon=` value to a file for the given size and scale. Absolute
+/// paths are used as they are.
+pub fn resolve_icon(icon_name: &str, icon_theme: &str, size: u16, scale: u16) -> Option<String> {
+    if icon_name.is_empty() {
+        return None;
+    }
+    if Path::new(icon_name).is_absolute() {
+        return Some(icon_name.to_string());
+    }
+    lookup(icon_name)
+        .with_cache()
+        .with_size(size)
+        .with_scale(scale)
+        .with_theme(icon_theme)
+        .find()
+        .map(|path| path.to_string_lossy().to_string())
 }
 
 pub struct DesktopEntryManager {
     locales: Vec<String>,
-    desktop_entries: Vec<DesktopEntry>,
+    search_paths: Vec<PathBuf>,
+    desktop_entries: HashMap<PathBuf, DesktopEntry>,
 }
 
 fn normalize_name(s: &str) -> String {
@@ -25,45 +136,177 @@ fn normalize_name(s: &str) -> String {
         .collect()
 }
 
-impl DesktopEntryManager {
-    pub fn new() -> Self {
-        let locales = get_languages_from_env();
-        let paths = Iter::new(default_paths());
-        let desktop_entries = paths
-            .filter_map(|path| DesktopEntry::from_path(path, Some(&locales)).ok())
-            .collect();
+fn is_desktop_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "desktop")
+}
+
+/// Collects every desktop file below `dir`. Unlike `freedesktop_desktop_entry::Iter`
+/// the paths are not canonicalized, so they match the paths reported by `notify`.
+fn collect_desktop_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = read_dir.filter_map(Result::ok).map(|e| e.path()).collect();
+    paths.sort_unstable();
 
-        Self {
-            locales,
-            desktop_entries,
+    for path in paths {
+        if path.is_dir() {
+            collect_desktop_files(&path, out);
+        } else if path.is_file() && is_desktop_file(&path) {
+            out.push(path);
         }
     }
+}
+
+impl DesktopEntryManager {
+    pub fn new() -> Self {
+        let mut manager = Self {
+            locales: Vec::new(),
+            search_paths: Vec::new(),
+            desktop_entries: HashMap::new(),
+        };
+        manager.refresh();
+        manager
+    }
 
     pub fn refresh(&mut self) {
         self.locales = get_languages_from_env();
-        let paths = Iter::new(default_paths());
-        self.desktop_entries = paths
-            .filter_map(|path| DesktopEntry::from_path(path, Some(&self.locales)).ok())
+        self.search_paths = default_paths().collect();
+        self.desktop_entries.clear();
+
+        let mut files = Vec::new();
+        for dir in &self.search_paths {
+            collect_desktop_files(dir, &mut files);
+        }
+        for path in files {
+            self.load(path);
+        }
+    }
+
+    /// Re-parses only the given files or directories. Returns whether any
+    /// tracked entry was added, changed or removed.
+    pub fn update_paths(&mut self, paths: &[PathBuf]) -> bool {
+        let mut changed = false;
+
+        for path in paths {
+            if path.is_dir() {
+                let mut files = Vec::new();
+                collect_desktop_files(path, &mut files);
+                let present: HashSet<&PathBuf> = files.iter().collect();
+                let before = self.desktop_entries.len();
+                self.desktop_entries
+                    .retain(|p, _| !p.starts_with(path) || present.contains(p));
+                changed |= before != self.desktop_entries.len();
+                for file in files {
+                    changed |= self.load(file);
+                }
+            } else if is_desktop_file(path) && path.is_file() {
+                changed |= self.load(path.clone());
+            } else {
+                // Either a removed desktop file or a removed directory.
+                let before = self.desktop_entries.len();
+                self.desktop_entries.retain(|p, _| !p.starts_with(path));
+                changed |= before != self.desktop_entries.len();
+            }
+        }
+
+        changed
+    }
+
+    fn load(&mut self, path: PathBuf) -> bool {
+        match DesktopEntry::from_path(path.clone(), Some(&self.locales)) {
+            Ok(entry) => {
+                debug!("indexed {}", path.display());
+                self.desktop_entries.insert(path, entry);
+                true
+            }
+            Err(_) => self.desktop_entries.remove(&path).is_some(),
+        }
+    }
+
+    /// Tracked entries in lookup order: search path priority first, then path.
+    fn ordered_entries(&self) -> Vec<&DesktopEntry> {
+        let rank = |path: &Path| {
+            self.search_paths
+                .iter()
+                .position(|dir| path.starts_with(dir))
+                .unwrap_or(usize::MAX)
+        };
+        let mut entries: Vec<(usize, &PathBuf, &DesktopEntry)> = self
+            .desktop_entries
+            .iter()
+            .map(|(path, entry)| (rank(path), path, entry))
             .collect();
+        entries.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        entries.into_iter().map(|(_, _, entry)| entry).collect()
+    }
+
+    /// Actions listed in `Actions=` that have both a name and an `Exec`.
+    fn actions(&self, entry: &DesktopEntry) -> Vec<EntryAction> {
+        entry
+            .actions()
+            .unwrap_or_default()
+            .into_iter()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| {
+                let name = entry.action_name(id, &self.locales)?;
+                let exec = entry.action_exec(id)?;
+                Some(EntryAction {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    exec: exec.to_string(),
+                    icon: entry
+                        .action_entry(id, "Icon")
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+            })
+            .collect()
     }
 
+    /// The entries to index, plus every desktop file that was skipped along
+    /// with the reason. `desktops` are the current desktop names.
     pub fn get_normalized_entries(
         &self,
         icon_theme: &str,
         icon_size: &u16,
         blacklist: Vec<String>,
-    ) -> Vec<NormalDesktopEntry> {
+        desktops: &[String],
+    ) -> (Vec<NormalDesktopEntry>, Vec<FilteredDesktopEntry>) {
         let mut entries = Vec::new();
+        let mut filtered = Vec::new();
         let blacklist: HashSet<String> = blacklist.into_iter().collect();
         let mut seen_names: std::collections::HashSet<String> = std::collections::HashSet::new();
+        let mut seen_ids: HashMap<&str, &Path> = HashMap::new();
+
+        for entry in self.ordered_entries() {
+            let mut skip = |reason: FilterReason| {
+                debug!("skipping {}: {}", entry.path.display(), reason);
+                filtered.push(FilteredDesktopEntry {
+                    appid: entry.appid.clone(),
+                    path: entry.path.clone(),
+                    reason,
+                });
+            };
+
+            // Hidden entries still claim their ID so they hide the files they shadow.
+            if let Some(winner) = seen_ids.get(entry.appid.as_str()) {
+                skip(FilterReason::Shadowed(winner.to_path_buf()));
+                continue;
+            }
+            seen_ids.insert(&entry.appid, &entry.path);
 
-        for entry in &self.desktop_entries {
             let name = match entry.name(&self.locales) {
                 Some(name) => name.to_string(),
-                None => continue,
+                None => {
+                    skip(FilterReason::MissingName);
+                    continue;
+                }
             };
 
             if blacklist.contains(&entry.appid) {
+                skip(FilterReason::Blacklisted);
                 continue;
             }
 
@@ -72,6 +315,12 @@ impl DesktopEntryManager {
             let mut index_opt: Option<usize> = None;
 
             if entry.no_display() {
+                skip(FilterReason::NoDisplay);
+                continue;
+            }
+
+            if let Some(reason) = spec_filter(entry, desktops) {
+                skip(reason);
                 continue;
             }
 
@@ -88,15 +337,18 @@ impl DesktopEntryManager {
                         if !entry.icon().unwrap_or_default().is_empty() {
                             replace = true;
                         } else {
+                            skip(FilterReason::Duplicate(entries[index].appid.clone()));
                             continue;
                         }
                     } else {
+                        skip(FilterReason::Duplicate(entries[index].appid.clone()));
                         continue;
                     }
                 }
             }
 
             let icon_name = entry.icon().unwrap_or_default().to_string();
+            let mut resolved_icon_name = icon_name.clone();
             let mut icon_path = String::new();
 
             if !icon_name.is_empty() && Path::new(&icon_name).is_absolute() {
@@ -114,7 +366,8 @@ impl DesktopEntryManager {
             }
 
             if icon_path.is_empty() {
-                let icon = lookup("application-x-executable")
+                resolved_icon_name = FALLBACK_ICON.to_string();
+                let icon = lookup(FALLBACK_ICON)
                     .with_size(*icon_size)
                     .with_cache()
                     .with_theme(icon_theme)
@@ -126,20 +379,60 @@ impl DesktopEntryManager {
             let exec = entry.exec().unwrap_or_default().to_string();
             let icon = icon_path;
             let comment = entry.comment(&self.locales).unwrap_or_default().to_string();
+            let generic_name = entry
+                .generic_name(&self.locales)
+                .unwrap_or_default()
+                .to_string();
+            let keywords = entry
+                .keywords(&self.locales)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect();
+            let categories = entry
+                .categories()
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(String::from)
+                .collect();
+            let terminal = entry.terminal();
+            let working_dir = entry.desktop_entry("Path").unwrap_or_default().to_string();
+            let prefers_non_default_gpu = entry.prefers_non_default_gpu();
+            let startup_wm_class = entry.startup_wm_class().unwrap_or_default().to_string();
             let appid = entry.appid.clone();
+            let actions = self.actions(entry);
 
             let nde = NormalDesktopEntry {
                 app_name,
                 exec,
+                terminal,
+                working_dir,
+                prefers_non_default_gpu,
+                startup_wm_class,
                 icon,
+                icon_name,
+                resolved_icon_name,
                 comment,
+                generic_name,
+                keywords,
+                categories,
                 appid,
+                path: entry.path.clone(),
+                actions,
             };
 
             if replace == true {
                 if let Some(index) = index_opt {
                     debug!("replacing");
-                    entries[index] = nde;
+                    let replaced = std::mem::replace(&mut entries[index], nde);
+                    filtered.push(FilteredDesktopEntry {
+                        reason: FilterReason::Duplicate(entries[index].appid.clone()),
+                        appid: replaced.appid,
+                        path: replaced.path,
+                    });
                 }
             } else {
                 entries.push(nde);
@@ -147,6 +440,6 @@ impl DesktopEntryManager {
             seen_names.insert(name.clone());
         }
 
-        entries
+        (entries, filtered)
     }
-}
\ No newline at end of file
+}
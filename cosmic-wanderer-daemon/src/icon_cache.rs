@@ -0,0 +1,152 @@
+use dirs::cache_dir;
+use log::{debug, error};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+pub type RenderedIcon = (Vec<u8>, u32, u32);
+
+/// Rendered icons keyed by source path, mtime, file length, target size and
+/// scale, kept in memory and as QOI files under `$XDG_CACHE_HOME`.
+///
+/// Every key looked up since the last [`IconCache::collect_garbage`] counts as
+/// live; everything else is dropped from memory and disk when it runs.
+pub struct IconCache {
+    dir: Option<PathBuf>,
+    memory: Mutex<HashMap<u64, RenderedIcon>>,
+    used: Mutex<HashSet<u64>>,
+}
+
+/// FNV-1a, used instead of `DefaultHasher` so file names stay stable across
+/// Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn cache_key(path: &str, size: i32, scale: u32) -> Option<u64> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    let key = format!(
+        "{}\0{}\0{}\0{}\0{}",
+        path,
+        mtime,
+        metadata.len(),
+        size,
+        scale
+    );
+    Some(fnv1a(key.as_bytes()))
+}
+
+fn file_name(key: u64) -> String {
+    format!("{:016x}.qoi", key)
+}
+
+impl IconCache {
+    pub fn new() -> Self {
+        let dir = cache_dir().map(|mut dir| {
+            dir.push("cosmic-wanderer");
+            dir.push("icons");
+            dir
+        });
+        let dir = dir.filter(|dir| match fs::create_dir_all(dir) {
+            Ok(()) => true,
+            Err(e) => {
+                error!(
+                    "icon cache disabled, cannot create {}: {}",
+                    dir.display(),
+                    e
+                );
+                false
+            }
+        });
+
+        Self {
+            dir,
+            memory: Mutex::new(HashMap::new()),
+            used: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns the cached render of `path`, calling `render` only on a miss.
+    /// An empty render marks an icon that failed to load and is cached too.
+    pub fn get_or_render<F>(&self, path: &str, size: i32, scale: u32, render: F) -> RenderedIcon
+    where
+        F: FnOnce() -> RenderedIcon,
+    {
+        let Some(key) = cache_key(path, size, scale) else {
+            return render();
+        };
+        self.used.lock().unwrap().insert(key);
+
+        if let Some(icon) = self.memory.lock().unwrap().get(&key) {
+            return icon.clone();
+        }
+
+        let icon = match self.read(key) {
+            Some(icon) => icon,
+            None => {
+                let icon = render();
+                self.write(key, &icon.0);
+                icon
+            }
+        };
+        self.memory.lock().unwrap().insert(key, icon.clone());
+        icon
+    }
+
+    fn read(&self, key: u64) -> Option<RenderedIcon> {
+        let data = fs::read(self.dir.as_ref()?.join(file_name(key))).ok()?;
+        if data.is_empty() {
+            // A previous render failed; don't retry until the file changes.
+            return Some((data, 0, 0));
+        }
+        let header = qoi::decode_header(&data).ok()?;
+        Some((data, header.width, header.height))
+    }
+
+    fn write(&self, key: u64, data: &[u8]) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let path = dir.join(file_name(key));
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, &path)) {
+            error!("failed to write icon cache {}: {}", path.display(), e);
+        }
+    }
+
+    /// Drops every render that was not looked up since the previous call.
+    pub fn collect_garbage(&self) {
+        let used = std::mem::take(&mut *self.used.lock().unwrap());
+        self.memory
+            .lock()
+            .unwrap()
+            .retain(|key, _| used.contains(key));
+
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return;
+        };
+        let live: HashSet<String> = used.iter().map(|key| file_name(*key)).collect();
+        for entry in read_dir.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !live.contains(&name) {
+                debug!("removing stale icon cache {}", name);
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    error!("failed to remove {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+    }
+}
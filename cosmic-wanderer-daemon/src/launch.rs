@@ -0,0 +1,189 @@
+//! Programs started on behalf of the launcher. The daemon outlives the
+//! launcher window, so it can watch a program for a grace period and keep
+//! reading its stderr for as long as anything writes to it.
+
+use log::{debug, error, warn};
+use notify_rust::Notification;
+use std::io::{self, PipeReader, Read};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Lines of stderr included when reporting a failed launch.
+const STDERR_TAIL_LINES: usize = 5;
+
+/// Bytes of stderr kept while watching a launch, plenty for the last lines.
+const STDERR_TAIL_BYTES: usize = 8192;
+
+/// The end of a watched program's stderr.
+struct StderrTail {
+    /// None once the program has closed its end.
+    pipe: Option<PipeReader>,
+    buf: Vec<u8>,
+}
+
+impl StderrTail {
+    /// Waits up to `timeout` for output and keeps the end of it. Returns
+    /// whether anything was read.
+    fn read(&mut self, timeout: Duration) -> bool {
+        let Some(pipe) = &mut self.pipe else {
+            thread::sleep(timeout);
+            return false;
+        };
+        let mut fd = libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 {
+            return false;
+        }
+        let mut chunk = [0; 4096];
+        match pipe.read(&mut chunk) {
+            Ok(0) | Err(_) => {
+                self.pipe = None;
+                false
+            }
+            Ok(n) => {
+                self.buf.extend_from_slice(&chunk[..n]);
+                let excess = self.buf.len().saturating_sub(STDERR_TAIL_BYTES);
+                self.buf.drain(..excess);
+                true
+            }
+        }
+    }
+
+    fn last_lines(&self) -> String {
+        let text = String::from_utf8_lossy(&self.buf);
+        let lines: Vec<&str> = text
+            .lines()
+            .rev()
+            .filter(|line| !line.trim().is_empty())
+            .take(STDERR_TAIL_LINES)
+            .collect();
+        lines.into_iter().rev().collect::<Vec<_>>().join("\n")
+    }
+}
+
+fn describe_status(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with status {}", code),
+        (None, Some(signal)) => format!("was killed by signal {}", signal),
+        (None, None) => format!("exited with {}", status),
+    }
+}
+
+fn send_notification(message: &str) {
+    let _ = Notification::new()
+        .summary("Cosmic wanderer")
+        .body(message)
+        .show();
+}
+
+/// Starts `argv` in a new session with exactly `env` and returns its pid.
+/// Exiting with an error within `grace` is reported with the end of its
+/// stderr; a zero `grace` discards stderr and leaves it unwatched.
+pub fn spawn(
+    name: String,
+    argv: &[String],
+    env: &[(String, String)],
+    working_dir: &str,
+    grace: Duration,
+) -> io::Result<u32> {
+    let Some((cmd, args)) = argv.split_first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty command"));
+    };
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .env_clear()
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null());
+    if !working_dir.is_empty() {
+        command.current_dir(working_dir);
+    }
+
+    let mut stderr = None;
+    if grace.is_zero() {
+        command.stderr(Stdio::null());
+    } else {
+        let (reader, writer) = io::pipe()?;
+        command.stderr(writer);
+        stderr = Some(reader);
+    }
+
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    debug!("launching {:?}", command);
+    let mut child = command.spawn()?;
+    // Drop our copy of the write end so reading sees EOF once the program
+    // and whatever it forked are done with it.
+    drop(command);
+    let pid = child.id();
+    match stderr {
+        Some(stderr) => watch(child, name, stderr, grace),
+        None => {
+            thread::spawn(move || child.wait());
+        }
+    }
+    Ok(pid)
+}
+
+/// Reports `child` if it fails before `grace` has passed. Its stderr is read
+/// until every writer has closed it, so nothing it or its children write
+/// later blocks or fails.
+fn watch(mut child: Child, name: String, stderr: PipeReader, grace: Duration) {
+    thread::spawn(move || {
+        let mut tail = StderrTail {
+            pipe: Some(stderr),
+            buf: Vec::new(),
+        };
+        let deadline = Instant::now() + grace;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("cannot watch {}: {}", name, e);
+                    break None;
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break None;
+            }
+            tail.read(remaining.min(Duration::from_millis(50)));
+        };
+
+        match status {
+            Some(status) if !status.success() => {
+                while tail.read(Duration::ZERO) {}
+                let mut msg = format!("{} {}", name, describe_status(status));
+                let lines = tail.last_lines();
+                if !lines.is_empty() {
+                    msg.push_str(":\n");
+                    msg.push_str(&lines);
+                }
+                error!("{}", msg);
+                send_notification(&msg);
+            }
+            Some(_) => {}
+            None => {
+                thread::spawn(move || child.wait());
+            }
+        }
+
+        if let Some(mut pipe) = tail.pipe {
+            let _ = io::copy(&mut pipe, &mut io::sink());
+        }
+    });
+}
@@ -1,51 +1,193 @@
-use freedesktop_desktop_entry::default_paths;
-use image::codecs::png::PngEncoder;
-use log::error;
-use notify::event::{CreateKind, EventKind, ModifyKind, RemoveKind};
-use notify::{RecursiveMode, Watcher, recommended_watcher};
+use log::{debug, error, warn};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use rsvg::Loader;
-use serde::Serialize;
-use std::fs::{self, File};
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::{error::Error, io::Write, sync::mpsc::channel, thread};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::os::unix::net::UnixStream;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use std::{error::Error, io::Read};
 
 mod entries;
-use entries::{DesktopEntryManager, NormalDesktopEntry};
+use entries::{DesktopEntryManager, FALLBACK_ICON, NormalDesktopEntry, resolve_icon};
 
 mod config;
+mod icon_cache;
+mod launch;
+use icon_cache::{IconCache, RenderedIcon};
+
+mod server;
+mod watcher;
+
+use cosmic_wanderer_protocol::socket::{Response, ResponseFrame, write_frame};
+use cosmic_wanderer_protocol::{
+    EntryDiff, EntryInfo, EntryRecord, FilteredEntry, IconImage, MAX_ICON_DIMENSION, cache, runtime,
+};
+
+pub struct Daemon {
+    pub config: config::Config,
+    manager: Mutex<DesktopEntryManager>,
+    pub index: RwLock<Vec<EntryRecord>>,
+    /// Desktop files skipped by the last rebuild.
+    pub filtered: RwLock<Vec<FilteredEntry>>,
+    subscribers: Mutex<Vec<UnixStream>>,
+    icons: IconCache,
+    render_pool: ThreadPool,
+}
+
+impl Daemon {
+    fn new(config: config::Config) -> Self {
+        let render_pool = ThreadPoolBuilder::new()
+            .num_threads(config.general.render_threads)
+            .thread_name(|i| format!("icon-render-{}", i))
+            .build()
+            .unwrap();
+        let daemon = Self {
+            config,
+            manager: Mutex::new(DesktopEntryManager::new()),
+            index: RwLock::new(Vec::new()),
+            filtered: RwLock::new(Vec::new()),
+            subscribers: Mutex::new(Vec::new()),
+            icons: IconCache::new(),
+            render_pool,
+        };
+        daemon.rebuild(&daemon.manager.lock().unwrap());
+        daemon
+    }
+
+    /// Re-reads every desktop file and rebuilds the index, returning its size.
+    pub fn reload(&self) -> usize {
+        let mut manager = self.manager.lock().unwrap();
+        manager.refresh();
+        self.rebuild(&manager)
+    }
+
+    /// Re-parses only the changed paths and rebuilds the index if that
+    /// affected any entry.
+    pub fn update(&self, paths: &[PathBuf]) {
+        let mut manager = self.manager.lock().unwrap();
+        if manager.update_paths(paths) {
+            self.rebuild(&manager);
+        }
+    }
+
+    fn rebuild(&self, manager: &DesktopEntryManager) -> usize {
+        let (entries, filtered) = manager.get_normalized_entries(
+            &self.config.general.icon_theme,
+            &self.config.general.icon_size,
+            self.config.general.blacklist.clone(),
+            &self.config.general.desktop_names(),
+        );
+        *self.filtered.write().unwrap() = filtered
+            .into_iter()
+            .map(|f| FilteredEntry {
+                appid: f.appid,
+                path: f.path.to_string_lossy().to_string(),
+                reason: f.reason.to_string(),
+            })
+            .collect();
+        let data = build_entries(
+            &entries,
+            &self.config.general,
+            &self.icons,
+            &self.render_pool,
+        );
+        self.icons.collect_garbage();
+        save_compressed(Path::new(&self.config.general.cache_path), &data);
+
+        let count = data.len();
+        let diff = {
+            let mut index = self.index.write().unwrap();
+            let diff = EntryDiff::between(&index, &data);
+            *index = data;
+            diff
+        };
+        if !diff.is_empty() {
+            self.broadcast(diff);
+        }
+        count
+    }
+
+    /// The icon of `appid` at `size` and `scale`, falling back to the base
+    /// size. Variants that aren't pre-rendered are looked up in the theme
+    /// and rendered on demand.
+    pub fn icon(&self, appid: &str, size: Option<u16>, scale: Option<u16>) -> Option<IconImage> {
+        let size = size.unwrap_or(self.config.general.icon_size);
+        let scale = scale.unwrap_or(1).max(1);
+        let (icon_name, base_path) = {
+            let index = self.index.read().unwrap();
+            let entry = index.iter().find(|e| e.info.appid == appid)?;
+            if let Some(icon) = entry
+                .icons
+                .iter()
+                .find(|icon| icon.size == size && icon.scale == scale)
+            {
+                return Some(icon.clone());
+            }
+            (entry.info.icon_name.clone(), entry.info.icon.clone())
+        };
+        let theme = &self.config.general.icon_theme;
+        let path = resolve_icon(&icon_name, theme, size, scale)
+            .or_else(|| resolve_icon(FALLBACK_ICON, theme, size, scale))
+            .unwrap_or(base_path);
+
+        let (qoi, width, height) = match render_icon(&self.icons, &path, size, scale) {
+            icon if icon.0.is_empty() => placeholder_icon(&self.icons, theme, size, scale),
+            icon => icon,
+        };
+        Some(IconImage {
+            size,
+            scale,
+            width,
+            height,
+            qoi,
+        })
+    }
 
-#[derive(Serialize)]
-struct EntryOut<'a> {
-    name: &'a str,
-    appid: &'a str,
-    app_name: &'a str,
-    exec: &'a str,
-    comment: &'a str,
-    icon: &'a str,
-    icon_compressed: Vec<u8>,
-    width: u32,
-    height: u32,
+    /// Registers a connection that receives an `entries_changed` frame after every rebuild.
+    pub fn subscribe(&self, stream: UnixStream) {
+        if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(1))) {
+            error!("failed to set subscriber write timeout: {}", e);
+        }
+        self.subscribers.lock().unwrap().push(stream);
+    }
+
+    fn broadcast(&self, diff: EntryDiff) {
+        let frame = ResponseFrame::new(Response::EntriesChanged { diff });
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain_mut(|stream| match write_frame(stream, &frame) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("dropping subscriber: {}", e);
+                false
+            }
+        });
+    }
 }
 
 use cairo::{Context, Format, ImageSurface, Rectangle};
-use image::{ColorType, ImageEncoder, ImageReader};
+use image::{ImageReader, Limits};
 
-pub fn render_svg_to_compressed(path: &str, width: i32, height: i32) -> (Vec<u8>, u32, u32) {
-    let handle = Loader::new().read_path(path).unwrap();
+pub fn render_svg_to_compressed(
+    path: &str,
+    width: i32,
+    height: i32,
+) -> Result<RenderedIcon, Box<dyn Error>> {
+    let handle = Loader::new().read_path(path)?;
 
-    let mut surface = ImageSurface::create(Format::ARgb32, width, height).unwrap();
+    let mut surface = ImageSurface::create(Format::ARgb32, width, height)?;
 
     {
-        let cr = Context::new(&surface).unwrap();
+        let cr = Context::new(&surface)?;
         let renderer = rsvg::CairoRenderer::new(&handle);
 
-        renderer
-            .render_document(&cr, &Rectangle::new(0.0, 0.0, width as f64, height as f64))
-            .unwrap();
+        renderer.render_document(&cr, &Rectangle::new(0.0, 0.0, width as f64, height as f64))?;
     }
 
-    let mut data = surface.data().unwrap().to_vec();
+    let mut data = surface.data()?.to_vec();
 
     for px in data.chunks_exact_mut(4) {
         let a = px[3] as u32;
@@ -64,100 +206,225 @@ pub fn render_svg_to_compressed(path: &str, width: i32, height: i32) -> (Vec<u8>
             px[2] = 0;
         }
     }
-    let mut buf = Vec::new();
-    let encoder = PngEncoder::new(&mut buf);
+    let buf = qoi::encode_to_vec(&data, width as u32, height as u32)?;
+
+    Ok((buf, width as u32, height as u32))
+}
+
+fn load_raster_compressed(path: &str) -> Result<RenderedIcon, Box<dyn Error>> {
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_ICON_DIMENSION);
+    limits.max_image_height = Some(MAX_ICON_DIMENSION);
+    reader.limits(limits);
 
-    encoder
-        .write_image(
-            &data,
-            width.try_into().unwrap(),
-            height.try_into().unwrap(),
-            ColorType::Rgba8.into(),
-        )
-        .ok();
+    let rgba_img = reader.decode()?.to_rgba8();
+    let (w, h) = rgba_img.dimensions();
+    let buf = qoi::encode_to_vec(rgba_img.as_raw(), w, h)?;
 
-    (buf, width as u32, height as u32)
+    Ok((buf, w, h))
 }
 
-fn load_icon_compressed(path: &str, width: i32, height: i32) -> (Vec<u8>, u32, u32) {
+fn is_svg(path: &str) -> bool {
+    if path.ends_with(".svg") {
+        return true;
+    }
+
+    let mut buffer = [0u8; 64];
+    let Ok(bytes_read) = File::open(path).and_then(|mut f| f.read(&mut buffer)) else {
+        return false;
+    };
+    std::str::from_utf8(&buffer[..bytes_read])
+        .is_ok_and(|s| s.contains("<svg") || s.contains("<?xml"))
+}
+
+/// Loads and encodes an icon, or returns `None` with a logged warning when
+/// the file is missing, malformed or too large.
+fn load_icon_compressed(path: &str, width: i32, height: i32) -> Option<RenderedIcon> {
     let std_path = std::path::Path::new(path);
+    if path.is_empty() {
+        return None;
+    }
     if !std_path.exists() || std_path.is_dir() {
-        return (vec![], 0, 0);
+        warn!("icon {} does not exist", path);
+        return None;
     }
 
-    if path.ends_with(".svg") {
-        return render_svg_to_compressed(path, width, height);
-    }
+    // librsvg and the image decoders should return errors, but a hostile file
+    // must never take the whole daemon down with it.
+    let result = panic::catch_unwind(|| {
+        if is_svg(path) {
+            render_svg_to_compressed(path, width, height)
+        } else {
+            load_raster_compressed(path)
+        }
+    });
 
-    if let Ok(mut file) = File::open(path) {
-        let mut buffer = [0u8; 64];
-        if let Ok(bytes_read) = std::io::Read::read(&mut file, &mut buffer) {
-            if let Ok(utf8_str) = std::str::from_utf8(&buffer[..bytes_read]) {
-                if utf8_str.contains("<svg") || utf8_str.contains("<?xml") {
-                    return render_svg_to_compressed(path, width, height);
-                }
-            }
+    match result {
+        Ok(Ok(icon)) => Some(icon),
+        Ok(Err(e)) => {
+            warn!("failed to load icon {}: {}", path, e);
+            None
+        }
+        Err(_) => {
+            warn!("panicked while loading icon {}", path);
+            None
         }
     }
+}
 
-    if let Ok(reader) = ImageReader::open(path) {
-        if let Ok(guessed_reader) = reader.with_guessed_format() {
-            if let Ok(img) = guessed_reader.decode() {
-                let rgba_img = img.to_rgba8();
-                let (w, h) = rgba_img.dimensions();
-
-                let mut buf = Vec::new();
-                let encoder = PngEncoder::new(&mut buf);
-                encoder
-                    .write_image(rgba_img.as_raw(), w, h, ColorType::Rgba8.into())
-                    .ok();
+/// The theme's generic application icon, rendered through the icon cache,
+/// or a plain grey square if even that can't be loaded. Used for every entry
+/// whose own icon failed.
+fn placeholder_icon(icons: &IconCache, icon_theme: &str, size: u16, scale: u16) -> RenderedIcon {
+    let px = u32::from(size) * u32::from(scale);
+    if let Some(path) = resolve_icon(FALLBACK_ICON, icon_theme, size, scale) {
+        let icon = render_icon(icons, &path, size, scale);
+        if !icon.0.is_empty() {
+            return icon;
+        }
+    }
 
-                return (buf, w, h);
-            }
+    let inset = px / 8;
+    let mut data = vec![0u8; (px * px * 4) as usize];
+    for (i, pixel) in data.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i as u32 % px, i as u32 / px);
+        if (inset..px - inset).contains(&x) && (inset..px - inset).contains(&y) {
+            pixel.copy_from_slice(&[128, 128, 128, 160]);
         }
     }
+    let buf = qoi::encode_to_vec(&data, px, px).unwrap_or_default();
+    (buf, px, px)
+}
 
-    (vec![], 0, 0)
+/// Renders `path` for `size` logical pixels at `scale` through the icon
+/// cache. A failed render comes back empty.
+fn render_icon(icons: &IconCache, path: &str, size: u16, scale: u16) -> RenderedIcon {
+    let px = i32::from(size) * i32::from(scale);
+    icons.get_or_render(path, px, scale.into(), || {
+        load_icon_compressed(path, px, px).unwrap_or_default()
+    })
 }
 
-fn format_entries(entries: &[NormalDesktopEntry], size: i32) -> String {
-    let data: Vec<EntryOut> = entries
+/// Renders each distinct (icon file, size, scale) once on `pool`, then
+/// assembles the records in the order of `entries`.
+fn build_entries(
+    entries: &[NormalDesktopEntry],
+    general: &config::GeneralConfig,
+    icons: &IconCache,
+    pool: &ThreadPool,
+) -> Vec<EntryRecord> {
+    let variants = general.icon_variants();
+    // Looked up on every rebuild so the cache keeps them.
+    let placeholders: HashMap<(u16, u16), RenderedIcon> = variants
+        .iter()
+        .map(|&(size, scale)| {
+            let icon = placeholder_icon(icons, &general.icon_theme, size, scale);
+            ((size, scale), icon)
+        })
+        .collect();
+    let base = (general.icon_size, 1);
+
+    // Themes can ship different files per size, so each variant gets its own
+    // lookup; the base variant was already resolved by the entry manager.
+    let paths: Vec<Vec<String>> = entries
         .iter()
         .map(|e| {
-            let (icon_compressed, width, height) = load_icon_compressed(&e.icon, size, size);
-            EntryOut {
-                name: &e.app_name,
-                appid: &e.appid,
-                app_name: &e.app_name,
-                exec: &e.exec,
-                comment: &e.comment,
-                icon: &e.icon,
-                icon_compressed,
-                width,
-                height,
-            }
+            variants
+                .iter()
+                .map(|&(size, scale)| {
+                    if (size, scale) == base {
+                        return e.icon.clone();
+                    }
+                    resolve_icon(&e.resolved_icon_name, &general.icon_theme, size, scale)
+                        .unwrap_or_else(|| e.icon.clone())
+                })
+                .collect()
         })
         .collect();
-    serde_json::to_string(&data).unwrap()
+
+    let mut seen = HashSet::new();
+    let jobs: Vec<(&str, u16, u16)> = paths
+        .iter()
+        .flat_map(|paths| {
+            paths
+                .iter()
+                .zip(&variants)
+                .map(|(path, &(size, scale))| (path.as_str(), size, scale))
+        })
+        .filter(|job| seen.insert(*job))
+        .collect();
+
+    let rendered: HashMap<(&str, u16, u16), RenderedIcon> = pool.install(|| {
+        jobs.par_iter()
+            .map(|&(path, size, scale)| {
+                ((path, size, scale), render_icon(icons, path, size, scale))
+            })
+            .collect()
+    });
+
+    entries
+        .iter()
+        .zip(&paths)
+        .map(|(e, paths)| {
+            let images = paths
+                .iter()
+                .zip(&variants)
+                .map(|(path, &(size, scale))| {
+                    let (qoi, width, height) = match &rendered[&(path.as_str(), size, scale)] {
+                        icon if icon.0.is_empty() => placeholders[&(size, scale)].clone(),
+                        icon => icon.clone(),
+                    };
+                    IconImage {
+                        size,
+                        scale,
+                        width,
+                        height,
+                        qoi,
+                    }
+                })
+                .collect();
+            EntryRecord {
+                info: EntryInfo {
+                    appid: e.appid.clone(),
+                    app_name: e.app_name.clone(),
+                    exec: e.exec.clone(),
+                    terminal: e.terminal,
+                    working_dir: e.working_dir.clone(),
+                    prefers_non_default_gpu: e.prefers_non_default_gpu,
+                    startup_wm_class: e.startup_wm_class.clone(),
+                    comment: e.comment.clone(),
+                    generic_name: e.generic_name.clone(),
+                    keywords: e.keywords.clone(),
+                    categories: e.categories.clone(),
+                    icon: e.icon.clone(),
+                    icon_name: e.icon_name.clone(),
+                    desktop_file: e.path.to_string_lossy().to_string(),
+                    actions: e.actions.clone(),
+                },
+                icons: images,
+            }
+        })
+        .collect()
 }
 
-fn save_compressed(entries: &[NormalDesktopEntry], size: i32) {
-    let data = {
-        let locked = entries;
-        format_entries(&locked, size)
+fn save_compressed(path: &Path, entries: &[EntryRecord]) {
+    let data = match cache::encode(entries) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("failed to encode entry cache: {}", e);
+            return;
+        }
     };
 
-    let mut location = PathBuf::from_str("/tmp/cosmic-wanderer/").unwrap();
-    if let Err(e) = fs::create_dir_all(&location) {
-        log::error!("Failed to create directory: {}", e);
+    if let Some(dir) = path.parent()
+        && let Err(e) = runtime::ensure_private_dir(dir)
+    {
+        error!("refusing to write {}: {}", path.display(), e);
+        return;
     }
-    location.push("entries.txt");
-    let mut file = File::create(location).unwrap();
-
-    if let Err(e) = file.write_all(data.as_bytes()) {
-        error!("write failed {}", e);
-    } else {
-        file.flush().unwrap();
+    if let Err(e) = runtime::write_atomic(path, &data) {
+        error!("failed to write {}: {}", path.display(), e);
     }
 }
 
@@ -171,47 +438,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     #[cfg(not(feature = "config_file"))]
     let config = config::default_config();
 
-    let mut manager = DesktopEntryManager::new();
-
-    let entries = manager.get_normalized_entries(
-        &config.general.icon_theme,
-        &config.general.icon_size,
-        config.general.blacklist.clone(),
-    );
-    save_compressed(&entries, config.general.icon_size.into());
+    let daemon = Arc::new(Daemon::new(config));
+    let _server = server::spawn(daemon.clone())?;
 
-    let (tx, rx) = channel();
-    let mut watcher = recommended_watcher(tx)?;
-    drop(entries);
-
-    for path in default_paths() {
-        if path.exists() {
-            watcher.watch(&path, RecursiveMode::Recursive)?;
-        }
-    }
-
-    let file_watcher = thread::spawn(move || {
-        for res in rx {
-            match res {
-                Ok(event) => match event.kind {
-                    EventKind::Modify(ModifyKind::Data(_))
-                    | EventKind::Create(CreateKind::Any)
-                    | EventKind::Remove(RemoveKind::Any) => {
-                        manager.refresh();
-
-                        let entries = manager.get_normalized_entries(
-                            &config.general.icon_theme,
-                            &config.general.icon_size,
-                            config.general.blacklist.clone(),
-                        );
-                        save_compressed(&entries, config.general.icon_size.into());
-                    }
-                    _ => {}
-                },
-                Err(e) => error!("watch error: {:?}", e),
-            }
-        }
-    });
+    let (_watcher, file_watcher) = watcher::spawn(daemon)?;
 
     file_watcher.join().unwrap();
 
@@ -0,0 +1,204 @@
+use log::{debug, error, info, warn};
+use std::fs;
+use std::io::{self, BufReader};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{Daemon, launch};
+use cosmic_wanderer_protocol::socket::{
+    PROTOCOL_VERSION, Request, RequestFrame, Response, ResponseFrame, read_frame, write_frame,
+};
+use cosmic_wanderer_protocol::{MAX_ICON_DIMENSION, runtime};
+
+pub fn spawn(daemon: Arc<Daemon>) -> io::Result<JoinHandle<()>> {
+    let path = daemon.config.general.socket_path();
+    if let Some(dir) = path.parent() {
+        runtime::ensure_private_dir(dir)?;
+    }
+    remove_stale_socket(&path)?;
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    info!("listening on {}", path.display());
+
+    let owner = unsafe { libc::geteuid() };
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => match peer_uid(&stream) {
+                    Ok(uid) if uid == owner => {
+                        let daemon = daemon.clone();
+                        thread::spawn(move || handle_client(stream, &daemon));
+                    }
+                    Ok(uid) => warn!("rejected connection from uid {}", uid),
+                    Err(e) => error!("failed to read peer credentials: {}", e),
+                },
+                Err(e) => error!("socket accept failed: {}", e),
+            }
+        }
+    }))
+}
+
+/// Removes a socket left behind by a daemon that didn't shut down cleanly.
+/// Fails if another daemon is still answering on it or if `path` is not a
+/// socket at all.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another daemon is already listening on {}", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            info!("removing stale socket {}", path.display());
+            fs::remove_file(path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+fn handle_client(stream: UnixStream, daemon: &Daemon) {
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    loop {
+        let response = match read_frame::<_, RequestFrame>(&mut reader) {
+            Ok(None) => return,
+            Ok(Some(frame)) if frame.version != PROTOCOL_VERSION => Response::Error {
+                message: format!(
+                    "unsupported protocol version {} (daemon speaks {})",
+                    frame.version, PROTOCOL_VERSION
+                ),
+            },
+            Ok(Some(RequestFrame {
+                request: Request::Subscribe,
+                ..
+            })) => {
+                subscribe(&stream, daemon);
+                return;
+            }
+            Ok(Some(frame)) => handle_request(frame.request, daemon),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => Response::Error {
+                message: format!("malformed request: {}", e),
+            },
+            Err(e) => {
+                debug!("client read failed: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = write_frame(&mut writer, &ResponseFrame::new(response)) {
+            debug!("client write failed: {}", e);
+            return;
+        }
+    }
+}
+
+fn subscribe(stream: &UnixStream, daemon: &Daemon) {
+    let mut writer = stream;
+    if let Err(e) = write_frame(&mut writer, &ResponseFrame::new(Response::Subscribed)) {
+        debug!("client write failed: {}", e);
+        return;
+    }
+    match stream.try_clone() {
+        Ok(stream) => daemon.subscribe(stream),
+        Err(e) => error!("failed to register subscriber: {}", e),
+    }
+}
+
+fn handle_request(request: Request, daemon: &Daemon) -> Response {
+    match request {
+        Request::ListEntries => {
+            let index = daemon.index.read().unwrap();
+            Response::Entries {
+                entries: index.iter().map(|e| e.info.clone()).collect(),
+            }
+        }
+        Request::GetEntry { appid } => {
+            let index = daemon.index.read().unwrap();
+            match index.iter().find(|e| e.info.appid == appid) {
+                Some(entry) => Response::Entry {
+                    entry: Box::new(entry.info.clone()),
+                },
+                None => not_found(&appid),
+            }
+        }
+        Request::GetIcon { appid, size, scale } => {
+            let pixels = u32::from(size.unwrap_or(0)) * u32::from(scale.unwrap_or(1));
+            if pixels > MAX_ICON_DIMENSION {
+                return Response::Error {
+                    message: format!("icon size {} exceeds {}", pixels, MAX_ICON_DIMENSION),
+                };
+            }
+            match daemon.icon(&appid, size, scale) {
+                Some(icon) => Response::Icon { icon },
+                None => not_found(&appid),
+            }
+        }
+        Request::Reload => Response::Reloaded {
+            count: daemon.reload(),
+        },
+        Request::ListFiltered => Response::Filtered {
+            entries: daemon.filtered.read().unwrap().clone(),
+        },
+        Request::Subscribe => unreachable!("handled in handle_client"),
+        Request::Launch {
+            name,
+            argv,
+            env,
+            working_dir,
+            grace_period_ms,
+        } => {
+            let grace = Duration::from_millis(grace_period_ms);
+            match launch::spawn(name, &argv, &env, &working_dir, grace) {
+                Ok(pid) => Response::Launched { pid },
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            }
+        }
+    }
+}
+
+fn not_found(appid: &str) -> Response {
+    Response::Error {
+        message: format!("no entry with appid {}", appid),
+    }
+}
@@ -0,0 +1,79 @@
+use freedesktop_desktop_entry::default_paths;
+use log::{debug, error};
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher, recommended_watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::Daemon;
+
+/// Upper bound on how long a steady stream of events can delay a rebuild.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(5);
+
+pub fn spawn(daemon: Arc<Daemon>) -> notify::Result<(RecommendedWatcher, JoinHandle<()>)> {
+    let (tx, rx) = channel();
+    let mut watcher = recommended_watcher(tx)?;
+
+    for path in default_paths() {
+        if path.exists() {
+            watcher.watch(&path, RecursiveMode::Recursive)?;
+        }
+    }
+
+    let quiet = Duration::from_millis(daemon.config.general.watch_debounce_ms);
+    let handle = thread::spawn(move || watch_loop(&daemon, &rx, quiet));
+
+    Ok((watcher, handle))
+}
+
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+    )
+}
+
+fn collect(res: notify::Result<Event>, paths: &mut HashSet<PathBuf>) {
+    match res {
+        // Renames report the old and the new path, so both end up re-checked.
+        Ok(event) if is_relevant(&event.kind) => paths.extend(event.paths),
+        Ok(_) => {}
+        Err(e) => error!("watch error: {:?}", e),
+    }
+}
+
+/// Gathers events until none arrive for `quiet`, then updates the index once
+/// for every path touched in that batch.
+fn watch_loop(daemon: &Daemon, rx: &Receiver<notify::Result<Event>>, quiet: Duration) {
+    while let Ok(first) = rx.recv() {
+        let mut paths = HashSet::new();
+        collect(first, &mut paths);
+
+        let started = Instant::now();
+        let mut disconnected = false;
+        while started.elapsed() < MAX_BATCH_DELAY {
+            match rx.recv_timeout(quiet) {
+                Ok(res) => collect(res, &mut paths),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        if !paths.is_empty() {
+            debug!("rebuilding after changes to {} paths", paths.len());
+            daemon.update(&paths.into_iter().collect::<Vec<_>>());
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Binary entry cache written by the daemon and read by the launcher at startup.
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! offset  size  field
+//!      0     8  magic, b"CWNDRIDX"
+//!      8     4  schema version, CACHE_VERSION
+//!     12     4  entry count
+//!     16     8  metadata length in bytes; metadata starts at offset 32
+//!     24     8  icon section offset
+//!     32     -  zstd-compressed JSON array of EntryInfo plus an `icons` list
+//!      -     -  icon section: QOI images, each starting on an 8-byte boundary
+//! ```
+//!
+//! Each element of `icons` describes one size/scale rendering with its
+//! `size`, `scale`, `width`, `height`, `offset` and `len`. Offsets are
+//! relative to the icon section, which itself starts on an 8-byte boundary,
+//! so a reader can map the file and hand each icon slice straight to the QOI
+//! decoder.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+
+use crate::{EntryInfo, EntryRecord, IconImage};
+
+pub const CACHE_MAGIC: [u8; 8] = *b"CWNDRIDX";
+/// Bumped whenever the header or the metadata changes shape.
+pub const CACHE_VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 32;
+const ICON_ALIGN: usize = 8;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct CachedIcon {
+    size: u16,
+    scale: u16,
+    width: u32,
+    height: u32,
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Serialize)]
+struct CachedEntryRef<'a> {
+    #[serde(flatten)]
+    info: &'a EntryInfo,
+    icons: Vec<CachedIcon>,
+}
+
+#[derive(Deserialize)]
+struct CachedEntry {
+    #[serde(flatten)]
+    info: EntryInfo,
+    icons: Vec<CachedIcon>,
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    Metadata(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::BadMagic => write!(f, "not a cosmic-wanderer entry cache"),
+            CacheError::UnsupportedVersion(found) => write!(
+                f,
+                "entry cache version {} is not supported by this launcher (expected {}); \
+                 make sure cosmic-wanderer and cosmic-wanderer-daemon are the same release \
+                 and restart the daemon",
+                found, CACHE_VERSION
+            ),
+            CacheError::Truncated => write!(f, "entry cache is truncated"),
+            CacheError::Metadata(e) => write!(f, "entry cache metadata is corrupt: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+pub fn encode(entries: &[EntryRecord]) -> io::Result<Vec<u8>> {
+    let mut icons = Vec::new();
+    let mut metadata = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let cached_icons = entry
+            .icons
+            .iter()
+            .map(|icon| {
+                let offset = icons.len();
+                icons.extend_from_slice(&icon.qoi);
+                icons.resize(icons.len().next_multiple_of(ICON_ALIGN), 0);
+                CachedIcon {
+                    size: icon.size,
+                    scale: icon.scale,
+                    width: icon.width,
+                    height: icon.height,
+                    offset: offset as u64,
+                    len: icon.qoi.len() as u64,
+                }
+            })
+            .collect();
+
+        metadata.push(CachedEntryRef {
+            info: &entry.info,
+            icons: cached_icons,
+        });
+    }
+
+    let compressed = zstd::encode_all(serde_json::to_vec(&metadata)?.as_slice(), ZSTD_LEVEL)?;
+    let icon_base = (HEADER_LEN + compressed.len()).next_multiple_of(ICON_ALIGN);
+
+    let mut out = Vec::with_capacity(icon_base + icons.len());
+    out.extend_from_slice(&CACHE_MAGIC);
+    out.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(icon_base as u64).to_le_bytes());
+    out.extend_from_slice(&compressed);
+    out.resize(icon_base, 0);
+    out.extend_from_slice(&icons);
+
+    Ok(out)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<EntryRecord>, CacheError> {
+    if data.len() < HEADER_LEN {
+        return Err(CacheError::Truncated);
+    }
+    if data[..8] != CACHE_MAGIC {
+        return Err(CacheError::BadMagic);
+    }
+    let version = read_u32(data, 8);
+    if version != CACHE_VERSION {
+        return Err(CacheError::UnsupportedVersion(version));
+    }
+
+    let metadata_len = read_u64(data, 16) as usize;
+    let icon_base = read_u64(data, 24) as usize;
+    let metadata = data
+        .get(HEADER_LEN..HEADER_LEN.saturating_add(metadata_len))
+        .ok_or(CacheError::Truncated)?;
+    let icons = data.get(icon_base..).ok_or(CacheError::Truncated)?;
+
+    let json = zstd::decode_all(metadata).map_err(|e| CacheError::Metadata(e.to_string()))?;
+    let cached: Vec<CachedEntry> =
+        serde_json::from_slice(&json).map_err(|e| CacheError::Metadata(e.to_string()))?;
+
+    cached
+        .into_iter()
+        .map(|entry| {
+            let images = entry
+                .icons
+                .into_iter()
+                .map(|icon| {
+                    let start = icon.offset as usize;
+                    let qoi = icons
+                        .get(start..start.saturating_add(icon.len as usize))
+                        .ok_or(CacheError::Truncated)?;
+                    Ok(IconImage {
+                        size: icon.size,
+                        scale: icon.scale,
+                        width: icon.width,
+                        height: icon.height,
+                        qoi: qoi.to_vec(),
+                    })
+                })
+                .collect::<Result<_, CacheError>>()?;
+            Ok(EntryRecord {
+                info: entry.info,
+                icons: images,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Everything the daemon knows about an application apart from its icon pixels.
+///
+/// New fields go here; the cache metadata and every socket message pick them
+/// up from this struct.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntryInfo {
+    pub appid: String,
+    pub app_name: String,
+    pub exec: String,
+    /// `Terminal=true`: the program needs to run inside a terminal emulator.
+    #[serde(default)]
+    pub terminal: bool,
+    /// `Path=`: the working directory to run the program in, empty for none.
+    #[serde(default)]
+    pub working_dir: String,
+    /// `PrefersNonDefaultGPU=true`: run on the discrete GPU if there is one.
+    #[serde(default)]
+    pub prefers_non_default_gpu: bool,
+    /// `StartupWMClass=`: the window class the program's windows will have.
+    #[serde(default)]
+    pub startup_wm_class: String,
+    pub comment: String,
+    /// Localized `GenericName=`, e.g. "Web Browser".
+    #[serde(default)]
+    pub generic_name: String,
+    /// Localized `Keywords=`.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// `Categories=`, e.g. `["Network", "WebBrowser"]`.
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Path of the icon file resolved at the daemon's base icon size.
+    pub icon: String,
+    /// The `Icon=` value, empty if the entry has none. `icon` may still be a
+    /// fallback in that case.
+    #[serde(default)]
+    pub icon_name: String,
+    /// Path of the desktop file this entry was read from.
+    #[serde(default)]
+    pub desktop_file: String,
+    /// `[Desktop Action ...]` groups listed in `Actions=`, in file order.
+    #[serde(default)]
+    pub actions: Vec<EntryAction>,
+}
+
+/// A secondary way to launch an application, such as "New Private Window".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntryAction {
+    /// The action identifier from `Actions=`.
+    pub id: String,
+    /// Localized `Name=` of the action.
+    pub name: String,
+    pub exec: String,
+    /// The action's `Icon=` value, empty if it has none.
+    pub icon: String,
+}
+
+/// Largest width or height of an icon, in pixels. The daemon doesn't decode
+/// or render anything bigger and the launcher doesn't decode it either.
+pub const MAX_ICON_DIMENSION: u32 = 1024;
+
+/// One rendering of an entry's icon. `size` is the logical size it was
+/// requested at and `scale` the output scale, so `width` is usually
+/// `size * scale`; raster icons keep their native dimensions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IconImage {
+    pub size: u16,
+    pub scale: u16,
+    pub width: u32,
+    pub height: u32,
+    /// QOI-encoded RGBA image, sent over the socket as base64.
+    #[serde(with = "base64_bytes")]
+    pub qoi: Vec<u8>,
+}
+
+/// Encodes bytes as a base64 string; JSON would otherwise spell out every
+/// byte as a decimal number.
+mod base64_bytes {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(de::Error::custom)
+    }
+}
+
+/// An indexed application together with its rendered icons, as stored in the
+/// cache and pushed to subscribers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntryRecord {
+    #[serde(flatten)]
+    pub info: EntryInfo,
+    /// One image per size and scale the daemon is configured to render.
+    pub icons: Vec<IconImage>,
+}
+
+impl EntryRecord {
+    /// The icon to show at `size` logical pixels on an output with `scale`:
+    /// the smallest one at least that many device pixels wide, or the largest
+    /// one if none is big enough.
+    pub fn best_icon(&self, size: u16, scale: u16) -> Option<&IconImage> {
+        let target = u32::from(size) * u32::from(scale.max(1));
+        self.icons
+            .iter()
+            .filter(|icon| icon.width >= target)
+            .min_by_key(|icon| icon.width)
+            .or_else(|| self.icons.iter().max_by_key(|icon| icon.width))
+    }
+}
+
+/// A desktop file the daemon left out of the index, and why.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FilteredEntry {
+    pub appid: String,
+    pub path: String,
+    pub reason: String,
+}
+
+/// Difference between two index snapshots, keyed by appid.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EntryDiff {
+    pub added: Vec<EntryRecord>,
+    pub changed: Vec<EntryRecord>,
+    pub removed: Vec<String>,
+}
+
+impl EntryDiff {
+    pub fn between(old: &[EntryRecord], new: &[EntryRecord]) -> Self {
+        let old_by_id: HashMap<&str, &EntryRecord> =
+            old.iter().map(|e| (e.info.appid.as_str(), e)).collect();
+        let new_ids: HashSet<&str> = new.iter().map(|e| e.info.appid.as_str()).collect();
+
+        let mut diff = Self::default();
+        for entry in new {
+            match old_by_id.get(entry.info.appid.as_str()) {
+                None => diff.added.push(entry.clone()),
+                Some(old_entry) if *old_entry != entry => diff.changed.push(entry.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .iter()
+            .filter(|e| !new_ids.contains(e.info.appid.as_str()))
+            .map(|e| e.info.appid.clone())
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
@@ -0,0 +1,440 @@
+//! Parsing and field-code expansion of `Exec=` values, following the Desktop
+//! Entry spec.
+//!
+//! The value is unescaped twice. First, as for every string value, `\s`,
+//! `\n`, `\t`, `\r` and `\\` stand for a space, newline, tab, carriage return
+//! and backslash. Then arguments are separated by unquoted spaces, and inside
+//! double quotes `\"`, `` \` ``, `\$` and `\\` stand for the escaped character,
+//! so a literal backslash in a quoted argument is written `\\\\`. Field codes
+//! are expanded after splitting, so a file name containing spaces or quotes
+//! stays a single argument.
+//!
+//! ```text
+//! %f  one local file      %F  every local file, each as its own argument
+//! %u  one URL or file     %U  every URL or file
+//! %i  --icon <Icon>       %c  the translated Name
+//! %k  the desktop file    %%  a literal %
+//! ```
+//!
+//! `%f` and `%u` take a single target, so launching with several targets runs
+//! one command per target. The deprecated `%d %D %n %N %v %m` are dropped.
+
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecError {
+    Empty,
+    UnterminatedQuote,
+    InvalidFieldCode(char),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::Empty => write!(f, "Exec is empty"),
+            ExecError::UnterminatedQuote => write!(f, "Exec has an unterminated quote"),
+            ExecError::InvalidFieldCode(c) => write!(f, "Exec has an invalid field code %{}", c),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(char),
+}
+
+/// Everything field codes can expand to.
+#[derive(Debug, Clone, Default)]
+pub struct ExecContext<'a> {
+    /// Translated `Name=`, for `%c`.
+    pub name: &'a str,
+    /// `Icon=` value, for `%i`.
+    pub icon: &'a str,
+    /// Path of the desktop file, for `%k`.
+    pub desktop_file: &'a str,
+    /// Files or URLs to open, for `%f %F %u %U`.
+    pub targets: &'a [String],
+}
+
+/// A parsed `Exec=` value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exec {
+    args: Vec<Vec<Part>>,
+}
+
+impl Exec {
+    /// Parses an `Exec=` value as written in the desktop file.
+    pub fn parse(exec: &str) -> Result<Self, ExecError> {
+        let exec = unescape_string(exec);
+        let mut args = Vec::new();
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        // Set by a quote, so `""` still produces an (empty) argument.
+        let mut in_arg = false;
+        let mut in_quotes = false;
+        let mut chars = exec.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    in_arg = true;
+                }
+                '\\' => {
+                    match chars.next() {
+                        Some(next @ ('"' | '`' | '$' | '\\')) => literal.push(next),
+                        // Not a valid escape; keep the backslash rather than guess.
+                        Some(next) => {
+                            literal.push('\\');
+                            literal.push(next);
+                        }
+                        None => literal.push('\\'),
+                    }
+                    in_arg = true;
+                }
+                '%' => match chars.next() {
+                    Some('%') => {
+                        literal.push('%');
+                        in_arg = true;
+                    }
+                    Some(code @ ('f' | 'F' | 'u' | 'U' | 'i' | 'c' | 'k')) => {
+                        if !literal.is_empty() {
+                            parts.push(Part::Literal(std::mem::take(&mut literal)));
+                        }
+                        parts.push(Part::Field(code));
+                        in_arg = true;
+                    }
+                    // Removed without starting an argument, so a lone one
+                    // doesn't leave an empty argument behind.
+                    Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                    Some(code) => return Err(ExecError::InvalidFieldCode(code)),
+                    None => return Err(ExecError::InvalidFieldCode(' ')),
+                },
+                c if c.is_whitespace() && !in_quotes => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    if in_arg {
+                        args.push(std::mem::take(&mut parts));
+                        in_arg = false;
+                    }
+                }
+                c => {
+                    literal.push(c);
+                    in_arg = true;
+                }
+            }
+        }
+
+        if in_quotes {
+            return Err(ExecError::UnterminatedQuote);
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if in_arg {
+            args.push(parts);
+        }
+        if args.is_empty() {
+            return Err(ExecError::Empty);
+        }
+        Ok(Self { args })
+    }
+
+    fn uses_single_target(&self) -> bool {
+        self.args
+            .iter()
+            .flatten()
+            .any(|part| matches!(part, Part::Field('f' | 'u')))
+    }
+
+    /// The command lines to run: one, or one per target when the Exec line
+    /// only accepts a single file or URL.
+    pub fn expand(&self, ctx: &ExecContext) -> Vec<Vec<String>> {
+        if self.uses_single_target() && ctx.targets.len() > 1 {
+            ctx.targets
+                .iter()
+                .map(|target| self.expand_with(ctx, std::slice::from_ref(target)))
+                .collect()
+        } else {
+            vec![self.expand_with(ctx, ctx.targets)]
+        }
+    }
+
+    fn expand_with(&self, ctx: &ExecContext, targets: &[String]) -> Vec<String> {
+        let files: Vec<String> = targets.iter().map(|t| to_local_path(t)).collect();
+        let mut argv = Vec::new();
+
+        for parts in &self.args {
+            match parts.as_slice() {
+                [Part::Field('F')] => argv.extend(files.iter().cloned()),
+                [Part::Field('U')] => argv.extend(targets.iter().cloned()),
+                [Part::Field('i')] => {
+                    if !ctx.icon.is_empty() {
+                        argv.push("--icon".to_string());
+                        argv.push(ctx.icon.to_string());
+                    }
+                }
+                _ => {
+                    let mut arg = String::new();
+                    let mut has_literal = false;
+                    for part in parts {
+                        match part {
+                            Part::Literal(text) => {
+                                arg.push_str(text);
+                                has_literal = true;
+                            }
+                            Part::Field('f') => arg.push_str(files.first().map_or("", |f| f)),
+                            Part::Field('u') => arg.push_str(targets.first().map_or("", |t| t)),
+                            Part::Field('i') => arg.push_str(ctx.icon),
+                            Part::Field('c') => arg.push_str(ctx.name),
+                            Part::Field('k') => arg.push_str(ctx.desktop_file),
+                            // Lists only make sense as a whole argument.
+                            Part::Field(_) => {}
+                        }
+                    }
+                    // A bare field code with nothing to expand to disappears
+                    // instead of becoming an empty argument.
+                    if has_literal || !arg.is_empty() || parts.is_empty() {
+                        argv.push(arg);
+                    }
+                }
+            }
+        }
+        argv
+    }
+}
+
+/// Applies the escapes every desktop entry string value may contain. Unknown
+/// ones, such as `\;` in lists, are kept for the caller to interpret.
+fn unescape_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(next) => {
+                out.push('\\');
+                out.push(next);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Turns a `file://` URL into a path for `%f`/`%F`; anything else is passed
+/// through unchanged.
+fn to_local_path(target: &str) -> String {
+    let Some(path) = target.strip_prefix("file://") else {
+        return target.to_string();
+    };
+    // Drop an optional host, as in file://localhost/home/user.
+    let path = match path.find('/') {
+        Some(slash) => &path[slash..],
+        None => path,
+    };
+    percent_decode(path)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Whether `name` resolves to an executable file, either as a path or
+/// through `$PATH`.
+pub fn find_executable(name: &str) -> bool {
+    let is_executable = |path: &Path| {
+        fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if name.contains('/') {
+        return is_executable(Path::new(name));
+    }
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(name)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(exec: &str, ctx: &ExecContext) -> Vec<Vec<String>> {
+        Exec::parse(exec).unwrap().expand(ctx)
+    }
+
+    fn targets(targets: &[&str]) -> Vec<String> {
+        targets.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_on_unquoted_whitespace() {
+        assert_eq!(
+            expand("  foo  --bar\tbaz ", &ExecContext::default()),
+            [["foo", "--bar", "baz"]]
+        );
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        assert_eq!(
+            expand(
+                r#""/opt/My App/run" "a \\"b\\" \\`c\\` \\$d \\\\e" x\y"#,
+                &ExecContext::default()
+            ),
+            [["/opt/My App/run", r#"a "b" `c` $d \e"#, r"x\y"]]
+        );
+        assert_eq!(
+            expand(r#"app "a \\\\ b" "x\\$y""#, &ExecContext::default()),
+            [["app", r"a \ b", "x$y"]]
+        );
+    }
+
+    #[test]
+    fn string_escapes_apply_before_splitting() {
+        assert_eq!(
+            expand(r#"foo\sbar "a\sb\tc\nd""#, &ExecContext::default()),
+            [["foo", "bar", "a b\tc\nd"]]
+        );
+        assert_eq!(unescape_string(r"a\\b\;c\"), r"a\b\;c\");
+    }
+
+    #[test]
+    fn empty_quotes_are_an_argument() {
+        assert_eq!(
+            expand(r#"foo "" bar"#, &ExecContext::default()),
+            [["foo", "", "bar"]]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Exec::parse(""), Err(ExecError::Empty));
+        assert_eq!(Exec::parse("   "), Err(ExecError::Empty));
+        assert_eq!(
+            Exec::parse(r#"foo "bar"#),
+            Err(ExecError::UnterminatedQuote)
+        );
+        assert_eq!(Exec::parse("foo %x"), Err(ExecError::InvalidFieldCode('x')));
+        assert_eq!(Exec::parse("foo %"), Err(ExecError::InvalidFieldCode(' ')));
+    }
+
+    #[test]
+    fn percent_literal_and_deprecated_codes() {
+        assert_eq!(
+            expand("printf 100%% %d %D %n %N %v %m", &ExecContext::default()),
+            [["printf", "100%"]]
+        );
+    }
+
+    #[test]
+    fn missing_targets_drop_bare_field_codes() {
+        assert_eq!(
+            expand("foo %f %F %u %U --file=%f", &ExecContext::default()),
+            [["foo", "--file="]]
+        );
+    }
+
+    #[test]
+    fn single_target_codes_run_once_per_target() {
+        let targets = targets(&["/a", "/b c"]);
+        let ctx = ExecContext {
+            targets: &targets,
+            ..Default::default()
+        };
+        assert_eq!(expand("open %f", &ctx), [["open", "/a"], ["open", "/b c"]]);
+        assert_eq!(
+            expand("open --url=%u", &ctx),
+            [["open", "--url=/a"], ["open", "--url=/b c"]]
+        );
+    }
+
+    #[test]
+    fn list_codes_take_every_target() {
+        let targets = targets(&["file:///tmp/a%20b", "https://example.com/x%20y"]);
+        let ctx = ExecContext {
+            targets: &targets,
+            ..Default::default()
+        };
+        assert_eq!(
+            expand("open %F", &ctx),
+            [["open", "/tmp/a b", "https://example.com/x%20y"]]
+        );
+        assert_eq!(
+            expand("open %U", &ctx),
+            [["open", "file:///tmp/a%20b", "https://example.com/x%20y"]]
+        );
+    }
+
+    #[test]
+    fn file_urls_become_paths() {
+        assert_eq!(
+            to_local_path("file:///home/me/a%20b.txt"),
+            "/home/me/a b.txt"
+        );
+        assert_eq!(to_local_path("file://localhost/etc/hosts"), "/etc/hosts");
+        assert_eq!(to_local_path("/already/a/path"), "/already/a/path");
+        assert_eq!(percent_decode("%e2%9c%93 100%"), "\u{2713} 100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn entry_codes() {
+        let ctx = ExecContext {
+            name: "My App",
+            icon: "my-app",
+            desktop_file: "/usr/share/applications/my-app.desktop",
+            targets: &[],
+        };
+        assert_eq!(
+            expand("app %i --title=%c --from %k", &ctx),
+            [[
+                "app",
+                "--icon",
+                "my-app",
+                "--title=My App",
+                "--from",
+                "/usr/share/applications/my-app.desktop"
+            ]]
+        );
+    }
+
+    #[test]
+    fn icon_code_without_icon_expands_to_nothing() {
+        let ctx = ExecContext {
+            name: "My App",
+            ..Default::default()
+        };
+        assert_eq!(expand("app %i %c", &ctx), [["app", "My App"]]);
+    }
+}
@@ -0,0 +1,13 @@
+//! Types and codecs shared by `cosmic-wanderer-daemon` and the `cosmic-wanderer`
+//! launcher: the entry schema, the binary entry cache, the socket protocol, the
+//! runtime directory they live in and `Exec=` parsing.
+
+pub mod cache;
+pub mod entry;
+pub mod exec;
+pub mod runtime;
+pub mod socket;
+
+pub use entry::{
+    EntryAction, EntryDiff, EntryInfo, EntryRecord, FilteredEntry, IconImage, MAX_ICON_DIMENSION,
+};
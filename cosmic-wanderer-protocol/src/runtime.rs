@@ -0,0 +1,142 @@
+//! Location and permissions of the runtime files shared by the daemon and the
+//! launcher.
+//!
+//! Everything lives in a private directory under `$XDG_RUNTIME_DIR`, or
+//! `/tmp/cosmic-wanderer-<uid>` when that is unset. Anything the launcher
+//! reads from there can make it run commands, so the directory and every file
+//! in it must belong to the current user and must not be writable by anyone
+//! else.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+const DIR_NAME: &str = "cosmic-wanderer";
+
+fn uid() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(DIR_NAME),
+        _ => PathBuf::from(format!("/tmp/{}-{}", DIR_NAME, uid())),
+    }
+}
+
+pub fn default_cache_path() -> PathBuf {
+    runtime_dir().join("entries.bin")
+}
+
+pub fn default_socket_path() -> PathBuf {
+    runtime_dir().join("daemon.sock")
+}
+
+/// Fails unless `metadata` belongs to the current user and is not writable by
+/// group or others.
+fn check_owner(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    if metadata.uid() != uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is owned by uid {}", path.display(), metadata.uid()),
+        ));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is writable by other users (mode {:o})",
+                path.display(),
+                metadata.mode() & 0o777
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Creates `dir` with mode 0700 if needed and checks that it is private.
+pub fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+    {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+    check_owner(dir, &metadata)
+}
+
+/// Checks that `path` and the directory containing it are private to the
+/// current user before it is trusted.
+pub fn check_private_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        check_owner(parent, &fs::metadata(parent)?)?;
+    }
+    check_owner(path, &fs::symlink_metadata(path)?)
+}
+
+/// Checks that the daemon socket can live at `path`: the directory holding
+/// it, if it exists yet, must be private to the current user. This rules out
+/// the shared `/tmp/comsic-wanderer.sock` older versions wrote into configs.
+pub fn check_socket_path(path: &Path) -> io::Result<()> {
+    let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    match fs::metadata(dir) {
+        Ok(metadata) => check_owner(dir, &metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// `configured` as the socket path, or `default_socket_path` if it is empty
+/// or fails `check_socket_path`, together with the reason it was rejected.
+pub fn resolve_socket_path(configured: &str) -> (PathBuf, Option<io::Error>) {
+    if configured.is_empty() {
+        return (default_socket_path(), None);
+    }
+    let path = PathBuf::from(configured);
+    match check_socket_path(&path) {
+        Ok(()) => (path, None),
+        Err(e) => (default_socket_path(), Some(e)),
+    }
+}
+
+/// Writes `data` to a 0600 temporary file next to `path` and renames it into
+/// place, so readers see either the old or the new contents, never a mix.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&tmp);
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
@@ -0,0 +1,166 @@
+//! Request/response protocol spoken on the daemon's `socket_path`.
+//!
+//! Every message is one line of JSON terminated by `\n`. A client writes a
+//! [`RequestFrame`] and the daemon answers with exactly one [`ResponseFrame`];
+//! a connection may carry any number of requests. Both frames carry the
+//! protocol `version`, and a request whose version differs from
+//! [`PROTOCOL_VERSION`] is answered with [`Response::Error`].
+//!
+//! ```text
+//! -> {"version":4,"request":{"type":"list_entries"}}
+//! <- {"version":4,"response":{"type":"entries","entries":[{"appid":"firefox",...}]}}
+//! -> {"version":4,"request":{"type":"get_entry","appid":"firefox"}}
+//! <- {"version":4,"response":{"type":"entry","entry":{"appid":"firefox",...}}}
+//! -> {"version":4,"request":{"type":"get_icon","appid":"firefox","size":32,"scale":2}}
+//! <- {"version":4,"response":{"type":"icon","size":32,"scale":2,"width":64,"height":64,"qoi":"cW9pZgAAAEAAAABABAA..."}}
+//! -> {"version":4,"request":{"type":"reload"}}
+//! <- {"version":4,"response":{"type":"reloaded","count":112}}
+//! -> {"version":4,"request":{"type":"list_filtered"}}
+//! <- {"version":4,"response":{"type":"filtered","entries":[{"appid":"org.gnome.Settings","path":"...","reason":"OnlyShowIn=GNOME; excludes the current desktop"}]}}
+//! -> {"version":4,"request":{"type":"launch","name":"Firefox","argv":["firefox"],"env":[["PATH","/usr/bin"],...],"working_dir":"/home/me","grace_period_ms":1500}}
+//! <- {"version":4,"response":{"type":"launched","pid":4242}}
+//! ```
+//!
+//! A `subscribe` request turns the connection into a push channel: after the
+//! `subscribed` reply the daemon stops reading from it and writes an
+//! `entries_changed` frame whenever a rebuild adds, removes or changes entries.
+//! Pushed entries carry their icon, so a subscriber never has to re-read the
+//! cache file.
+//!
+//! ```text
+//! -> {"version":4,"request":{"type":"subscribe"}}
+//! <- {"version":4,"response":{"type":"subscribed"}}
+//! <- {"version":4,"response":{"type":"entries_changed","diff":{"added":[...],"changed":[...],"removed":["vim"]}}}
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+
+use crate::{EntryDiff, EntryInfo, FilteredEntry, IconImage};
+
+/// Bumped whenever a request or response changes shape.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestFrame {
+    pub version: u32,
+    pub request: Request,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseFrame {
+    pub version: u32,
+    pub response: Response,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Every indexed entry, without icon data.
+    ListEntries,
+    /// A single entry by appid.
+    GetEntry { appid: String },
+    /// An entry's icon as a QOI image. Without `size` the daemon's base size
+    /// is returned; sizes it doesn't pre-render are rendered on demand.
+    GetIcon {
+        appid: String,
+        #[serde(default)]
+        size: Option<u16>,
+        #[serde(default)]
+        scale: Option<u16>,
+    },
+    /// Re-read all desktop files and rebuild the index.
+    Reload,
+    /// Every desktop file left out of the index, with the reason.
+    ListFiltered,
+    /// Receive an `entries_changed` frame after every rebuild.
+    Subscribe,
+    /// Start `argv` in its own session with exactly `env`, in `working_dir`.
+    /// The daemon reports it exiting with an error within `grace_period_ms`,
+    /// along with the end of its stderr, so the launcher needn't stay around.
+    Launch {
+        name: String,
+        argv: Vec<String>,
+        env: Vec<(String, String)>,
+        working_dir: String,
+        grace_period_ms: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Entries {
+        entries: Vec<EntryInfo>,
+    },
+    Entry {
+        entry: Box<EntryInfo>,
+    },
+    Icon {
+        #[serde(flatten)]
+        icon: IconImage,
+    },
+    Reloaded {
+        count: usize,
+    },
+    Filtered {
+        entries: Vec<FilteredEntry>,
+    },
+    Subscribed,
+    Launched {
+        pid: u32,
+    },
+    EntriesChanged {
+        diff: EntryDiff,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl RequestFrame {
+    pub fn new(request: Request) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            request,
+        }
+    }
+}
+
+impl ResponseFrame {
+    pub fn new(response: Response) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            response,
+        }
+    }
+}
+
+/// Reads the next non-empty line and decodes it. Returns `Ok(None)` on EOF.
+pub fn read_frame<R, T>(reader: &mut R) -> io::Result<Option<T>>
+where
+    R: BufRead,
+    T: for<'de> Deserialize<'de>,
+{
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, frame: &T) -> io::Result<()> {
+    let mut data = serde_json::to_vec(frame)?;
+    data.push(b'\n');
+    writer.write_all(&data)?;
+    writer.flush()
+}
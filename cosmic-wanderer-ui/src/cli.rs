@@ -0,0 +1,165 @@
+//! `cosmic-wanderer history ...`: managing launch history from the command
+//! line, e.g. to sync rankings between machines.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::history::*;
+
+const USAGE: &str = "\
+Usage: cosmic-wanderer history <command>
+
+Commands:
+  forget <appid>...                        drop the history of the given apps
+  reset                                    drop all history
+  export [--format json|toml] [FILE]       write history to FILE or stdout
+  import [--merge] [--format json|toml] FILE
+                                           replace history with FILE, or add
+                                           its counts with --merge
+
+FILE may be - for stdin or stdout. Without --format it is picked from the
+extension, and TOML is used otherwise. To open a file called `history` with
+the launcher, pass it as ./history.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Toml,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            _ => Err(format!("unknown format {}, expected json or toml", name)),
+        }
+    }
+
+    fn for_file(file: &str) -> Self {
+        match Path::new(file).extension().and_then(|e| e.to_str()) {
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+/// Options and operands of an export or import command.
+struct Args<'a> {
+    format: Option<Format>,
+    merge: bool,
+    files: Vec<&'a str>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args<'_>, String> {
+    let mut parsed = Args {
+        format: None,
+        merge: false,
+        files: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = args.next().ok_or("--format needs a value")?;
+                parsed.format = Some(Format::parse(name)?);
+            }
+            "--merge" => parsed.merge = true,
+            "-" => parsed.files.push("-"),
+            arg if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            arg => parsed.files.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn usage_error(message: &str) -> Box<dyn Error> {
+    format!("{}\n\n{}", message, USAGE).into()
+}
+
+/// Runs the `history` subcommand with the arguments that follow it.
+pub fn run(args: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    let half_life_days = config.search.half_life_days;
+    let Some((command, rest)) = args.split_first() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+
+    match command.as_str() {
+        "forget" => {
+            if rest.is_empty() {
+                return Err(usage_error("forget needs at least one appid"));
+            }
+            update_history(|history| {
+                let mut changed = false;
+                for appid in rest {
+                    if forget(history, appid) {
+                        changed = true;
+                    } else {
+                        eprintln!("no history for {}", appid);
+                    }
+                }
+                changed
+            })?;
+        }
+        "reset" => {
+            if !rest.is_empty() {
+                return Err(usage_error("reset takes no arguments"));
+            }
+            update_history(|history| {
+                let changed = !history.is_empty();
+                history.clear();
+                changed
+            })?;
+        }
+        "export" => {
+            let args = parse_args(rest).map_err(|e| usage_error(&e))?;
+            let file = match args.files.as_slice() {
+                [] => "-",
+                [file] => file,
+                _ => return Err(usage_error("export takes at most one file")),
+            };
+            let format = args.format.unwrap_or(Format::for_file(file));
+            // Read-only: a corrupt file is reported, not moved aside.
+            let history = parse_history(&get_history_file())?;
+            let data = match format {
+                Format::Json => serde_json::to_string_pretty(&history)? + "\n",
+                Format::Toml => toml::to_string(&history)?,
+            };
+            if file == "-" {
+                io::stdout().write_all(data.as_bytes())?;
+            } else {
+                fs::write(file, data)?;
+            }
+        }
+        "import" => {
+            let args = parse_args(rest).map_err(|e| usage_error(&e))?;
+            let [file] = args.files.as_slice() else {
+                return Err(usage_error("import needs exactly one file"));
+            };
+            let data = if *file == "-" {
+                let mut data = String::new();
+                io::stdin().read_to_string(&mut data)?;
+                data
+            } else {
+                fs::read_to_string(file)?
+            };
+            let imported: HistoryMap = match args.format.unwrap_or(Format::for_file(file)) {
+                Format::Json => serde_json::from_str(&data)?,
+                Format::Toml => toml::from_str(&data)?,
+            };
+            let count = imported.len();
+            update_history(|history| {
+                import_history(history, imported, args.merge, half_life_days);
+                true
+            })?;
+            eprintln!("imported history of {} apps", count);
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        other => return Err(usage_error(&format!("unknown command {}", other))),
+    }
+    Ok(())
+}
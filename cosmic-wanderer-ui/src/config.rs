@@ -1,12 +1,16 @@
+use cosmic_wanderer_protocol::runtime;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use slint::Color;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 #[cfg(feature = "config_file")]
 use {
     config::{Config as ConfigLoader, File},
     dirs::config_dir,
     std::fs,
-    std::path::{Path, PathBuf},
+    std::path::Path,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -59,9 +63,198 @@ pub struct ThemeConfig {
     pub animation_duration: u16,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Read through `DaemonConfig::socket_path`, which replaces an unsafe
+    /// value with the default.
+    pub socket_path: String,
+    /// The entry cache written by the daemon; must match its `cache_path`.
+    pub cache_path: String,
+}
+
+fn default_socket_path() -> String {
+    runtime::default_socket_path().to_string_lossy().to_string()
+}
+
+fn default_cache_path() -> String {
+    runtime::default_cache_path().to_string_lossy().to_string()
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            socket_path: default_socket_path(),
+            cache_path: default_cache_path(),
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// The daemon socket, falling back to the default the same way the
+    /// daemon does, so both agree when an old config names `/tmp`.
+    pub fn socket_path(&self) -> PathBuf {
+        let (path, rejected) = runtime::resolve_socket_path(&self.socket_path);
+        if let Some(e) = rejected {
+            warn!(
+                "ignoring daemon.socket_path {}: {}; using {}",
+                self.socket_path,
+                e,
+                path.display()
+            );
+        }
+        path
+    }
+}
+
+/// Percentages applied to the fuzzy match score of each entry field; the best
+/// weighted field decides the entry's rank. An empty query ranks by launch
+/// history instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub name_weight: u32,
+    pub generic_name_weight: u32,
+    pub keywords_weight: u32,
+    pub appid_weight: u32,
+    pub categories_weight: u32,
+    pub comment_weight: u32,
+    pub exec_weight: u32,
+    /// Days after which a launch counts half as much when ranking by
+    /// history. 0 never forgets.
+    pub half_life_days: f64,
+    /// Points added to an entry's score for each time it was picked after
+    /// typing the current query or one it is a prefix of, up to
+    /// `max_learned_selections` times. Picked entries are shown even if the
+    /// query doesn't match them otherwise.
+    pub learned_query_bonus: u32,
+    pub max_learned_selections: u32,
+}
+
+fn default_learned_query_bonus() -> u32 {
+    30
+}
+
+fn default_max_learned_selections() -> u32 {
+    5
+}
+
+fn default_half_life_days() -> f64 {
+    14.0
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            name_weight: 100,
+            generic_name_weight: 80,
+            keywords_weight: 70,
+            appid_weight: 60,
+            categories_weight: 40,
+            comment_weight: 30,
+            exec_weight: 20,
+            half_life_days: default_half_life_days(),
+            learned_query_bonus: default_learned_query_bonus(),
+            max_learned_selections: default_max_learned_selections(),
+        }
+    }
+}
+
+/// How launched programs are started.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LaunchStrategy {
+    /// Spawn the program in a new session as our child.
+    #[default]
+    Direct,
+    /// Prefix the command with `LaunchConfig::wrapper`.
+    Wrapper,
+    /// Prefix the command with `uwsm app --`.
+    Uwsm,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LaunchConfig {
+    #[serde(default)]
+    pub strategy: LaunchStrategy,
+    /// Command prefix for the `wrapper` strategy. `{appid}`, `{name}` and
+    /// `{rand}` are replaced in each argument, e.g.
+    /// `"systemd-run --user --scope --unit=app-{appid}-{rand}"`.
+    #[serde(default)]
+    pub wrapper: String,
+    /// Command that runs `Terminal=true` programs, with the program's argv
+    /// appended, e.g. `"foot"` or `"alacritty -e"`. Empty picks one from
+    /// `$TERMINAL`, `xdg-terminal-exec` or a list of common emulators.
+    #[serde(default)]
+    pub terminal: String,
+    /// Extra environment per appid, e.g. `[launch.env.firefox]` with
+    /// `MOZ_ENABLE_WAYLAND = "0"`.
+    #[serde(default)]
+    pub env: HashMap<String, HashMap<String, String>>,
+    /// Environment for `PrefersNonDefaultGPU=true` apps. Empty picks the
+    /// NVIDIA PRIME or Mesa `DRI_PRIME` variables based on the loaded driver.
+    #[serde(default)]
+    pub gpu_offload_env: HashMap<String, String>,
+    /// How long the daemon watches a launched program; exiting with an error
+    /// within this time is reported along with the end of its stderr. 0, or
+    /// no daemon running, starts programs unwatched.
+    #[serde(default = "default_grace_period_ms")]
+    pub grace_period_ms: u64,
+}
+
+fn default_grace_period_ms() -> u64 {
+    1500
+}
+
+impl Default for LaunchConfig {
+    fn default() -> Self {
+        Self {
+            strategy: LaunchStrategy::default(),
+            wrapper: String::new(),
+            terminal: String::new(),
+            env: HashMap::new(),
+            gpu_offload_env: HashMap::new(),
+            grace_period_ms: default_grace_period_ms(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryConfig {
+    /// Days an app can be missing from the index before its history is
+    /// dropped. 0 keeps it forever.
+    #[serde(default = "default_prune_after_days")]
+    pub prune_after_days: u32,
+    /// Old appid to new appid, for renames that matching `StartupWMClass=` or
+    /// `Exec=` doesn't catch, e.g. `nautilus = "org.gnome.Nautilus"`.
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+}
+
+fn default_prune_after_days() -> u32 {
+    90
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            prune_after_days: default_prune_after_days(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub theme: ThemeConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub launch: LaunchConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
 }
 
 #[cfg(not(feature = "quill_defaults"))]
@@ -154,6 +347,10 @@ pub fn default_config() -> Config {
             input_height: 70,
             animation_duration: 100,
         },
+        daemon: DaemonConfig::default(),
+        search: SearchConfig::default(),
+        launch: LaunchConfig::default(),
+        history: HistoryConfig::default(),
     }
 }
 
@@ -247,6 +444,10 @@ pub fn default_config() -> Config {
             input_height: 70,
             animation_duration: 0,
         },
+        daemon: DaemonConfig::default(),
+        search: SearchConfig::default(),
+        launch: LaunchConfig::default(),
+        history: HistoryConfig::default(),
     }
 }
 
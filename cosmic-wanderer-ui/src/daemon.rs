@@ -0,0 +1,141 @@
+use cosmic_wanderer_protocol::socket::{
+    Request, RequestFrame, Response, ResponseFrame, read_frame, write_frame,
+};
+use cosmic_wanderer_protocol::{EntryDiff, IconImage, runtime};
+use log::{debug, error};
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// How long to wait for the daemon to answer a single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub enum Update {
+    /// The subscription was (re-)established; changes made since the cache
+    /// was last read may have been missed.
+    Resync,
+    Diff(EntryDiff),
+}
+
+/// Keeps a subscription to the daemon open for the lifetime of the process,
+/// reconnecting whenever the daemon goes away.
+pub fn spawn_subscriber<F>(socket_path: PathBuf, on_update: F)
+where
+    F: Fn(Update) + Send + 'static,
+{
+    thread::spawn(move || {
+        loop {
+            if let Err(e) = subscribe(&socket_path, &on_update) {
+                debug!("subscription to {} failed: {}", socket_path.display(), e);
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+}
+
+fn subscribe<F>(socket_path: &Path, on_update: &F) -> io::Result<()>
+where
+    F: Fn(Update),
+{
+    // Pushed entries carry commands we may run, so only listen to a daemon
+    // owned by us.
+    runtime::check_private_file(socket_path)?;
+    let mut stream = UnixStream::connect(socket_path)?;
+    write_frame(&mut stream, &RequestFrame::new(Request::Subscribe))?;
+
+    let mut reader = BufReader::new(stream);
+    while let Some(frame) = read_frame::<_, ResponseFrame>(&mut reader)? {
+        match frame.response {
+            Response::Subscribed => {
+                debug!("subscribed to daemon updates");
+                // Even the first subscription opens after the cache was read,
+                // so a rebuild in between would otherwise go unnoticed.
+                on_update(Update::Resync);
+            }
+            Response::EntriesChanged { diff } => on_update(Update::Diff(diff)),
+            Response::Error { message } => {
+                error!("daemon refused subscription: {}", message);
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    debug!("daemon closed the subscription");
+    Ok(())
+}
+
+/// Asks the daemon for the icons of `appids` at `size` and `scale`, rendering
+/// any it hasn't pre-rendered, over a single connection. Apps the daemon no
+/// longer knows are left out.
+pub fn fetch_icons(
+    socket_path: &Path,
+    appids: &[&str],
+    size: u16,
+    scale: u16,
+) -> io::Result<HashMap<String, IconImage>> {
+    runtime::check_private_file(socket_path)?;
+    let stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = &stream;
+    let mut reader = BufReader::new(&stream);
+
+    let mut icons = HashMap::new();
+    for appid in appids {
+        let request = Request::GetIcon {
+            appid: appid.to_string(),
+            size: Some(size),
+            scale: Some(scale),
+        };
+        write_frame(&mut writer, &RequestFrame::new(request))?;
+        match read_frame::<_, ResponseFrame>(&mut reader)? {
+            Some(ResponseFrame {
+                response: Response::Icon { icon },
+                ..
+            }) => {
+                icons.insert(appid.to_string(), icon);
+            }
+            Some(ResponseFrame {
+                response: Response::Error { message },
+                ..
+            }) => debug!("no icon for {}: {}", appid, message),
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected response to get_icon",
+                ));
+            }
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+    Ok(icons)
+}
+
+/// Has the daemon start a program and watch it, so we can exit right away.
+/// Returns the program's pid.
+pub fn launch(socket_path: &Path, request: Request) -> io::Result<u32> {
+    runtime::check_private_file(socket_path)?;
+    let stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = &stream;
+    write_frame(&mut writer, &RequestFrame::new(request))?;
+    match read_frame::<_, ResponseFrame>(&mut BufReader::new(&stream))? {
+        Some(ResponseFrame {
+            response: Response::Launched { pid },
+            ..
+        }) => Ok(pid),
+        Some(ResponseFrame {
+            response: Response::Error { message },
+            ..
+        }) => Err(io::Error::other(message)),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected response to launch",
+        )),
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
@@ -1,12 +1,63 @@
-use serde::{Serialize, Deserialize};
-use std::{collections::HashMap, fs, path::PathBuf};
 use dirs::cache_dir;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use cosmic_wanderer_protocol::exec::{Exec, ExecContext};
+use cosmic_wanderer_protocol::runtime;
+
+use crate::config::HistoryConfig;
 
 use crate::*;
 
+const SECONDS_PER_DAY: f64 = 86400.0;
+/// Queries remembered per app; the least used is forgotten past this.
+const MAX_QUERIES_PER_APP: usize = 32;
+
+/// Launch history of one app. `score` is its frecency as of `last_used`: each
+/// launch adds 1, and the total halves every configured half-life.
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct HistoryData {
     pub usage_count: u32,
+    /// Decayed launch count at `last_used`.
+    #[serde(default)]
+    pub score: f64,
+    /// Unix time of the last launch, 0 in files written before frecency.
+    #[serde(default)]
+    pub last_used: u64,
+    /// How often the app was picked after typing each query.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub queries: HashMap<String, u32>,
+    /// Unix time the app was first found missing from the index, 0 while
+    /// it is installed.
+    #[serde(default)]
+    pub missing_since: u64,
+    /// `StartupWMClass=` and command line last seen for the app, to
+    /// recognise it if it comes back under another appid.
+    #[serde(default)]
+    pub wm_class: String,
+    #[serde(default)]
+    pub exec: String,
+}
+
+impl HistoryData {
+    /// `score` decayed from `last_used` to `now`.
+    pub fn frecency(&self, now: u64, half_life_days: f64) -> f64 {
+        if half_life_days <= 0.0 {
+            return self.score;
+        }
+        let age_days = now.saturating_sub(self.last_used) as f64 / SECONDS_PER_DAY;
+        self.score * 0.5f64.powf(age_days / half_life_days)
+    }
 }
 
 pub type HistoryMap = HashMap<String, HistoryData>;
@@ -19,34 +70,296 @@ pub fn get_history_file() -> PathBuf {
     path
 }
 
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Reads the history without changing anything: an empty one if there is
+/// none yet, or an `InvalidData` error if the file doesn't parse.
+pub(crate) fn parse_history(path: &Path) -> io::Result<HistoryMap> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut history: HistoryMap = toml::from_str(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e.message()),
+        )
+    })?;
+    migrate_counts(&mut history);
+    Ok(history)
+}
+
+/// Reads the history, or an empty one if there is none yet. A file that
+/// doesn't parse is moved aside and reported rather than silently replaced;
+/// failing to move it is an error so it isn't overwritten either.
+fn read_history(path: &Path) -> io::Result<HistoryMap> {
+    match parse_history(path) {
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            let backup = path.with_extension(format!("toml.corrupt-{}", now()));
+            fs::rename(path, &backup)?;
+            let msg = format!(
+                "History was unreadable and has been reset; the old file is at {} ({})",
+                backup.display(),
+                e
+            );
+            error!("{}", msg);
+            send_notification(&msg);
+            Ok(HashMap::new())
+        }
+        result => result,
+    }
+}
+
 pub fn load_history() -> HistoryMap {
-    let path = get_history_file();
-    if let Ok(data) = fs::read_to_string(&path) {
-        toml::from_str(&data).unwrap_or_default()
-    } else {
+    read_history(&get_history_file()).unwrap_or_else(|e| {
+        warn!("Cannot read history: {}", e);
         HashMap::new()
+    })
+}
+
+/// Records from before frecency only have a count; start their score from it
+/// as if they had all been used just now, and let it decay from there.
+fn migrate_counts(history: &mut HistoryMap) {
+    let now = now();
+    for data in history.values_mut() {
+        if data.last_used == 0 && data.usage_count > 0 {
+            data.score = f64::from(data.usage_count);
+            data.last_used = now;
+        }
     }
 }
 
-pub fn save_history(history: &HistoryMap) {
-    let toml_str = toml::to_string(history).unwrap();
-    fs::write(get_history_file(), toml_str).unwrap();
+/// Replaces the history file atomically, so a reader or a crash never sees
+/// it half written. Callers that modify the history should go through
+/// `update_history` so concurrent launchers don't lose each other's updates.
+pub fn save_history(history: &HistoryMap) -> io::Result<()> {
+    let toml_str =
+        toml::to_string(history).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    runtime::write_atomic(&get_history_file(), toml_str.as_bytes())
 }
 
-pub fn increment_usage(history: &mut HistoryMap, appid: &str) {
+/// Holds an exclusive lock on the history until dropped.
+fn lock_history() -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(get_history_file().with_extension("toml.lock"))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Loads and modifies the history while holding its lock, saving it if `f`
+/// returns true. Returns the history as it now is, including changes made by
+/// other launchers since it was last read.
+pub fn update_history(f: impl FnOnce(&mut HistoryMap) -> bool) -> io::Result<HistoryMap> {
+    let _lock = lock_history()?;
+    let mut history = read_history(&get_history_file())?;
+    if f(&mut history) {
+        save_history(&history)?;
+    }
+    Ok(history)
+}
+
+/// Drops everything known about `appid`. Returns whether there was anything.
+pub fn forget(history: &mut HistoryMap, appid: &str) -> bool {
+    history.remove(appid).is_some()
+}
+
+/// Adds `imported` to `history`, or replaces it entirely unless `merge`.
+pub fn import_history(
+    history: &mut HistoryMap,
+    mut imported: HistoryMap,
+    merge: bool,
+    half_life_days: f64,
+) {
+    migrate_counts(&mut imported);
+    if !merge {
+        *history = imported;
+        return;
+    }
+    let now = now();
+    for (appid, data) in imported {
+        merge_into(history.entry(appid).or_default(), data, now, half_life_days);
+    }
+}
+
+pub fn increment_usage(history: &mut HistoryMap, appid: &str, half_life_days: f64) {
+    let now = now();
     let entry = history.entry(appid.to_string()).or_default();
+    entry.score = entry.frecency(now, half_life_days) + 1.0;
+    entry.last_used = now;
     entry.usage_count += 1;
 }
 
+fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Remembers that `appid` was picked after typing `query`.
+pub fn record_query(history: &mut HistoryMap, appid: &str, query: &str) {
+    let query = normalize_query(query);
+    if query.is_empty() {
+        return;
+    }
+    let queries = &mut history.entry(appid.to_string()).or_default().queries;
+    // Make room before adding, so the new query isn't the one that goes.
+    // Ties go to the alphabetically first query to stay deterministic.
+    if !queries.contains_key(&query)
+        && queries.len() >= MAX_QUERIES_PER_APP
+        && let Some(rarest) = queries
+            .iter()
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+            .map(|(q, _)| q.clone())
+    {
+        queries.remove(&rarest);
+    }
+    *queries.entry(query).or_default() += 1;
+}
+
+/// How often `appid` was picked for `query` or a query it is a prefix of, so
+/// typing "te" counts picks made after typing "term".
+pub fn learned_selections(history: &HistoryMap, appid: &str, query: &str) -> u32 {
+    let query = normalize_query(query);
+    if query.is_empty() {
+        return 0;
+    }
+    history.get(appid).map_or(0, |data| {
+        data.queries
+            .iter()
+            .filter(|(q, _)| q.starts_with(&query))
+            .map(|(_, count)| count)
+            .sum()
+    })
+}
+
+/// The command line with field codes dropped, comparable across renames.
+fn exec_identity(exec: &str) -> String {
+    match Exec::parse(exec) {
+        Ok(parsed) => parsed.expand(&ExecContext::default()).concat().join(" "),
+        Err(_) => exec.to_string(),
+    }
+}
+
+/// The installed appid that the missing `appid` was renamed to: its
+/// configured alias, or else the one app with the same `StartupWMClass=` or
+/// command line. Several candidates are ambiguous and give none.
+fn renamed_to(
+    appid: &str,
+    data: &HistoryData,
+    entries: &[Entry],
+    config: &HistoryConfig,
+) -> Option<String> {
+    if let Some(alias) = config.aliases.get(appid) {
+        return entries
+            .iter()
+            .any(|e| &e.info.appid == alias)
+            .then(|| alias.clone());
+    }
+    let mut candidates = entries.iter().filter(|e| {
+        (!data.wm_class.is_empty() && e.info.startup_wm_class == data.wm_class)
+            || (!data.exec.is_empty() && exec_identity(&e.info.exec) == data.exec)
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(entry), None) => Some(entry.info.appid.clone()),
+        _ => None,
+    }
+}
+
+/// Folds the history of a renamed or imported app into its record.
+fn merge_into(target: &mut HistoryData, old: HistoryData, now: u64, half_life_days: f64) {
+    target.score = target.frecency(now, half_life_days) + old.frecency(now, half_life_days);
+    target.last_used = now;
+    target.usage_count += old.usage_count;
+    for (query, count) in old.queries {
+        *target.queries.entry(query).or_default() += count;
+    }
+}
+
+/// Moves the history of renamed apps to their new appid and drops apps that
+/// have been missing from `entries` for longer than configured. Returns
+/// whether anything changed.
+pub fn maintain_history(
+    history: &mut HistoryMap,
+    entries: &[Entry],
+    config: &HistoryConfig,
+    half_life_days: f64,
+) -> bool {
+    let now = now();
+    let mut changed = false;
+
+    for entry in entries {
+        if let Some(data) = history.get_mut(&entry.info.appid) {
+            let exec = exec_identity(&entry.info.exec);
+            if data.missing_since != 0
+                || data.wm_class != entry.info.startup_wm_class
+                || data.exec != exec
+            {
+                data.missing_since = 0;
+                data.wm_class = entry.info.startup_wm_class.clone();
+                data.exec = exec;
+                changed = true;
+            }
+        }
+    }
+
+    let installed: HashSet<&str> = entries.iter().map(|e| e.info.appid.as_str()).collect();
+    let missing: Vec<String> = history
+        .keys()
+        .filter(|appid| !installed.contains(appid.as_str()))
+        .cloned()
+        .collect();
+    let max_age = u64::from(config.prune_after_days) * SECONDS_PER_DAY as u64;
+    for appid in missing {
+        if let Some(new_appid) = renamed_to(&appid, &history[&appid], entries, config) {
+            info!("Moving history of {} to {}", appid, new_appid);
+            let old = history.remove(&appid).unwrap_or_default();
+            merge_into(
+                history.entry(new_appid).or_default(),
+                old,
+                now,
+                half_life_days,
+            );
+            changed = true;
+            continue;
+        }
+
+        let data = history.get_mut(&appid).unwrap();
+        if data.missing_since == 0 {
+            data.missing_since = now;
+            changed = true;
+        } else if max_age > 0 && now.saturating_sub(data.missing_since) > max_age {
+            info!("Forgetting history of uninstalled {}", appid);
+            history.remove(&appid);
+            changed = true;
+        }
+    }
+    changed
+}
+
 pub fn sorted_entries_by_usage(
     entries: &[Entry],
     usage: &HistoryMap,
+    half_life_days: f64,
 ) -> Vec<Entry> {
+    let now = now();
+    let frecency = |entry: &Entry| {
+        usage
+            .get(&entry.info.appid)
+            .map_or(0.0, |h| h.frecency(now, half_life_days))
+    };
     let mut sorted: Vec<_> = entries.to_vec(); // clones entries
-    sorted.sort_by(|a, b| {
-        let a_count = usage.get(&a.appid).map(|h| h.usage_count).unwrap_or(0);
-        let b_count = usage.get(&b.appid).map(|h| h.usage_count).unwrap_or(0);
-        b_count.cmp(&a_count)
-    });
+    sorted.sort_by(|a, b| frecency(b).total_cmp(&frecency(a)));
     sorted
-}
\ No newline at end of file
+}
@@ -0,0 +1,297 @@
+use log::{debug, error, warn};
+use std::hash::{BuildHasher, RandomState};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use cosmic_wanderer_protocol::exec::{Exec, ExecContext, find_executable};
+use cosmic_wanderer_protocol::socket::Request;
+
+use crate::config::{LaunchConfig, LaunchStrategy};
+use crate::{Entry, daemon, send_notification};
+
+/// Emulators tried in order when neither the config nor `$TERMINAL` names
+/// one, each with the arguments that make it run a command. A bare
+/// `$TERMINAL` also takes its arguments from here.
+const KNOWN_TERMINALS: &[&[&str]] = &[
+    &["cosmic-term", "-e"],
+    &["foot"],
+    &["kitty"],
+    &["alacritty", "-e"],
+    &["ghostty", "-e"],
+    &["wezterm", "start", "--"],
+    &["gnome-terminal", "--"],
+    &["konsole", "-e"],
+    &["xfce4-terminal", "-x"],
+    &["xterm", "-e"],
+];
+
+fn split_command(command: &str) -> Option<Vec<String>> {
+    Exec::parse(command)
+        .ok()?
+        .expand(&ExecContext::default())
+        .into_iter()
+        .next()
+}
+
+/// The command prefix that runs a program in a terminal window, or why there
+/// is none.
+fn terminal_command(config: &LaunchConfig) -> Result<Vec<String>, &'static str> {
+    if !config.terminal.trim().is_empty() {
+        return split_command(&config.terminal).ok_or("launch.terminal is not a valid command");
+    }
+    if let Ok(terminal) = std::env::var("TERMINAL")
+        && let Some(mut command) = split_command(&terminal)
+        && find_executable(&command[0])
+    {
+        if command.len() == 1 {
+            // A bare name gets the arguments that emulator needs, `-e` being
+            // the most common for ones we don't know.
+            let name = Path::new(&command[0])
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            match KNOWN_TERMINALS.iter().find(|known| known[0] == name) {
+                Some(known) => command.extend(known[1..].iter().map(|arg| arg.to_string())),
+                None => command.push("-e".to_string()),
+            }
+        }
+        return Ok(command);
+    }
+    if find_executable("xdg-terminal-exec") {
+        return Ok(vec!["xdg-terminal-exec".to_string()]);
+    }
+    KNOWN_TERMINALS
+        .iter()
+        .find(|command| find_executable(command[0]))
+        .map(|command| command.iter().map(|arg| arg.to_string()).collect())
+        .ok_or("no terminal emulator found, set launch.terminal in the config")
+}
+
+/// Eight hex digits that differ on every call, for unit names.
+fn random_suffix() -> String {
+    let hash = RandomState::new().hash_one(Instant::now());
+    format!("{:08x}", hash as u32)
+}
+
+/// The command prefix for the configured launch strategy.
+fn wrapper_command(config: &LaunchConfig, appid: &str, name: &str) -> Option<Vec<String>> {
+    match config.strategy {
+        LaunchStrategy::Direct => Some(Vec::new()),
+        LaunchStrategy::Uwsm => Some(vec!["uwsm".into(), "app".into(), "--".into()]),
+        LaunchStrategy::Wrapper => {
+            let rand = random_suffix();
+            let template = split_command(&config.wrapper)?;
+            Some(
+                template
+                    .iter()
+                    .map(|arg| {
+                        arg.replace("{appid}", appid)
+                            .replace("{name}", name)
+                            .replace("{rand}", &rand)
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Environment that makes a program render on the discrete GPU.
+fn gpu_offload_env(config: &LaunchConfig) -> Vec<(String, String)> {
+    if !config.gpu_offload_env.is_empty() {
+        return config
+            .gpu_offload_env
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+    }
+    let vars: &[(&str, &str)] = if Path::new("/proc/driver/nvidia").exists() {
+        &[
+            ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+            ("__GLX_VENDOR_LIBRARY_NAME", "nvidia"),
+            ("__VK_LAYER_NV_optimus", "NVIDIA_only"),
+        ]
+    } else {
+        &[("DRI_PRIME", "1")]
+    };
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// How to run a command apart from its argv.
+#[derive(Default)]
+pub struct LaunchOptions<'a> {
+    /// Desktop file ID, for the launch wrapper.
+    pub appid: &'a str,
+    pub terminal: bool,
+    /// Working directory, or empty to inherit ours.
+    pub working_dir: &'a str,
+    /// Variables set on top of our environment, later ones winning.
+    pub env: Vec<(String, String)>,
+}
+
+/// Runs `exec` through the daemon at `socket_path`, which watches it for
+/// early failures, or directly and unwatched if the daemon can't.
+pub fn launch(
+    exec: &str,
+    ctx: &ExecContext,
+    options: &LaunchOptions,
+    config: &LaunchConfig,
+    socket_path: &Path,
+    start_time: Instant,
+) {
+    let exec = match Exec::parse(exec) {
+        Ok(exec) => exec,
+        Err(e) => {
+            let msg = format!("Cannot launch {}: {}", ctx.name, e);
+            error!("[{:?}] {}", start_time.elapsed(), msg);
+            send_notification(&msg);
+            return;
+        }
+    };
+
+    let Some(mut prefix) = wrapper_command(config, options.appid, ctx.name) else {
+        let msg = format!(
+            "Cannot launch {}: launch.wrapper is not a valid command",
+            ctx.name
+        );
+        error!("[{:?}] {}", start_time.elapsed(), msg);
+        send_notification(&msg);
+        return;
+    };
+    if options.terminal {
+        match terminal_command(config) {
+            Ok(terminal) => prefix.extend(terminal),
+            Err(e) => {
+                let msg = format!("Cannot launch {}: {}", ctx.name, e);
+                error!("[{:?}] {}", start_time.elapsed(), msg);
+                send_notification(&msg);
+                return;
+            }
+        }
+    }
+
+    let working_dir = if options.working_dir.is_empty() {
+        None
+    } else if Path::new(options.working_dir).is_dir() {
+        Some(options.working_dir)
+    } else {
+        warn!(
+            "[{:?}] Path={} does not exist, using the current directory",
+            start_time.elapsed(),
+            options.working_dir
+        );
+        None
+    };
+
+    for argv in exec.expand(ctx) {
+        let argv: Vec<String> = prefix.iter().cloned().chain(argv).collect();
+        let Some((cmd, args)) = argv.split_first() else {
+            continue;
+        };
+
+        if config.grace_period_ms > 0 {
+            let request = Request::Launch {
+                name: ctx.name.to_string(),
+                argv: argv.clone(),
+                env: std::env::vars().chain(options.env.clone()).collect(),
+                working_dir: match working_dir {
+                    Some(dir) => dir.to_string(),
+                    None => std::env::current_dir()
+                        .map(|dir| dir.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                },
+                grace_period_ms: config.grace_period_ms,
+            };
+            match daemon::launch(socket_path, request) {
+                Ok(pid) => {
+                    debug!(
+                        "[{:?}] Daemon launched {:?} as {}",
+                        start_time.elapsed(),
+                        argv,
+                        pid
+                    );
+                    continue;
+                }
+                Err(e) => warn!(
+                    "[{:?}] Daemon cannot launch {}, starting it unwatched: {}",
+                    start_time.elapsed(),
+                    ctx.name,
+                    e
+                ),
+            }
+        }
+
+        let mut command = Command::new(cmd);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .envs(options.env.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = working_dir {
+            command.current_dir(dir);
+        }
+
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+
+        debug!(
+            "[{:?}] Launching command: {:?}",
+            start_time.elapsed(),
+            command
+        );
+
+        if let Err(e) = command.spawn() {
+            let msg = format!("Failed to spawn detached process: {}", e);
+            error!("[{:?}] {}", start_time.elapsed(), msg);
+            send_notification(&msg);
+        }
+    }
+}
+
+/// Launches `exec` with field codes filled in from the entry `appid`, if it
+/// is still known, and the files or URLs the launcher was started with.
+pub fn launch_for(
+    exec: &str,
+    appid: &str,
+    entries: &[Entry],
+    targets: &[String],
+    config: &LaunchConfig,
+    socket_path: &Path,
+    start_time: Instant,
+) {
+    let info = entries
+        .iter()
+        .find(|e| e.info.appid == appid)
+        .map(|e| &e.info);
+    let ctx = ExecContext {
+        name: info.map_or(appid, |info| &info.app_name),
+        icon: info.map_or("", |info| &info.icon_name),
+        desktop_file: info.map_or("", |info| &info.desktop_file),
+        targets,
+    };
+    let mut options = LaunchOptions {
+        appid,
+        ..Default::default()
+    };
+    if let Some(info) = info {
+        options.terminal = info.terminal;
+        options.working_dir = &info.working_dir;
+        if info.prefers_non_default_gpu {
+            options.env = gpu_offload_env(config);
+        }
+    }
+    if let Some(env) = config.env.get(appid) {
+        options
+            .env
+            .extend(env.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    launch(exec, &ctx, &options, config, socket_path, start_time);
+}
@@ -1,19 +1,15 @@
-use log::{debug, error};
+use log::{debug, error, warn};
 use notify_rust::Notification;
 use parking_lot::Mutex;
 use rayon::prelude::*;
-use shlex::Shlex;
 use slint::{Image, Model, ModelRc, VecModel, set_xdg_app_id};
 use slint::{Rgba8Pixel, SharedPixelBuffer};
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::{
     error::Error,
-    io::Read,
-    os::unix::{process::CommandExt},
-    process::{Command, Stdio},
     rc::Rc,
-    sync::Arc,
+    sync::{Arc, mpsc},
     thread,
 };
 use std::{fs, process, vec};
@@ -25,49 +21,97 @@ use std::cmp::Ordering;
 mod history;
 use crate::history::*;
 
+use cosmic_wanderer_protocol::cache::{self, CacheError};
+use cosmic_wanderer_protocol::runtime;
+use cosmic_wanderer_protocol::{EntryDiff, EntryInfo, EntryRecord, IconImage, MAX_ICON_DIMENSION};
+
 mod config;
 use config::config_color_to_slint;
 
+mod daemon;
+use daemon::Update;
+
+mod cli;
+mod launch;
+
 slint::include_modules!();
 
-#[derive(serde::Deserialize, Clone)]
-pub struct EntryIn {
-    appid: String,
-    app_name: String,
-    exec: String,
-    comment: String,
-    icon_compressed: Vec<u8>, // RGBA bytes from daemon compressed
-}
 #[derive(Clone)]
 pub struct Entry {
-    appid: String,
-    app_name: String,
-    exec: String,
-    comment: String,
-    icon: SharedPixelBuffer<Rgba8Pixel>, // RGBA bytes from daemon
+    info: EntryInfo,
+    /// RGBA bytes from the daemon, None if it sent none we could decode.
+    icon: Option<SharedPixelBuffer<Rgba8Pixel>>,
+}
+
+/// Splits `@category` tokens off a query, e.g. `"@game chess"` gives
+/// `(["game"], "chess")`.
+fn split_category_filters(text: &str) -> (Vec<&str>, String) {
+    let mut categories = Vec::new();
+    let mut rest = Vec::new();
+    for word in text.split_whitespace() {
+        match word.strip_prefix('@') {
+            Some(category) if !category.is_empty() => categories.push(category),
+            _ => rest.push(word),
+        }
+    }
+    (categories, rest.join(" "))
+}
+
+/// Whether `entry` is in every category, matched case-insensitively by prefix.
+fn in_categories(entry: &Entry, categories: &[&str]) -> bool {
+    categories.iter().all(|wanted| {
+        let wanted = wanted.to_lowercase();
+        entry
+            .info
+            .categories
+            .iter()
+            .any(|c| c.to_lowercase().starts_with(&wanted))
+    })
 }
 
-pub fn filter_and_sort_entries(text: &str, normalized_entries: &[Entry]) -> Vec<Entry> {
+pub fn filter_and_sort_entries(
+    text: &str,
+    normalized_entries: &[Entry],
+    weights: &config::SearchConfig,
+    history: &HistoryMap,
+) -> Vec<Entry> {
     let matcher = SkimMatcherV2::default();
+    let weighted = |field: &str, weight: u32| {
+        matcher
+            .fuzzy_match(field, text)
+            .map(|score| score * weight as i64 / 100)
+    };
 
     let mut matched_entries: Vec<(i64, Entry)> = normalized_entries
         .iter()
         .filter_map(|entry| {
-            let search_string = format!(
-                "{} {} {} {}",
-                entry.app_name, entry.comment, entry.appid, entry.exec
-            );
-
-            matcher
-                .fuzzy_match(&search_string, text)
-                .map(|score| (score, entry.clone()))
+            let info = &entry.info;
+            let score = [
+                weighted(&info.app_name, weights.name_weight),
+                weighted(&info.generic_name, weights.generic_name_weight),
+                weighted(&info.keywords.join(" "), weights.keywords_weight),
+                weighted(&info.appid, weights.appid_weight),
+                weighted(&info.categories.join(" "), weights.categories_weight),
+                weighted(&info.comment, weights.comment_weight),
+                weighted(&info.exec, weights.exec_weight),
+            ]
+            .into_iter()
+            .flatten()
+            .max();
+            let learned =
+                learned_selections(history, &info.appid, text).min(weights.max_learned_selections);
+            if score.is_none() && learned == 0 {
+                return None;
+            }
+            let bonus = i64::from(learned) * i64::from(weights.learned_query_bonus);
+            Some((score.unwrap_or(0) + bonus, entry.clone()))
         })
         .collect();
 
     matched_entries.sort_by(|a, b| {
         let score_cmp = b.0.cmp(&a.0);
         if score_cmp == Ordering::Equal {
-            a.1.app_name.len().cmp(&b.1.app_name.len())
+            a.1.info.app_name.len().cmp(&b.1.info.app_name.len())
         } else {
             score_cmp
         }
@@ -121,33 +165,167 @@ fn theme_from_config(theme: &config::ThemeConfig) -> ThemeSlint {
     }
 }
 
-fn fetch_entries_from_file() -> Vec<EntryIn> {
-    let mut location = PathBuf::from("/tmp/cosmic-wanderer/");
-    location.push("entries.txt");
+fn fetch_entries_from_file(location: &Path) -> Vec<EntryRecord> {
+    // The cache holds commands we are about to run, so only trust it if
+    // nobody else could have written it.
+    if let Err(e) = runtime::check_private_file(location) {
+        error!("ignoring {}: {}", location.display(), e);
+        return vec![];
+    }
 
-    let mut file = match File::open(location) {
-        Ok(f) => f,
+    let data = match fs::read(location) {
+        Ok(data) => data,
         Err(e) => {
             error!("file open failed: {}", e);
             return vec![];
         }
     };
 
-    let mut buf = String::new();
-
-    if file.read_to_string(&mut buf).is_ok() {
-        serde_json::from_str(&buf).unwrap_or_default()
-    } else {
-        error!("Failed to decompress or read from file");
-        vec![]
+    match cache::decode(&data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read {}: {}", location.display(), e);
+            if matches!(e, CacheError::UnsupportedVersion(_)) {
+                send_notification(&e.to_string());
+            }
+            vec![]
+        }
     }
 }
 
 fn decode_compressed_to_rgba(compressed_data: &[u8]) -> Option<SharedPixelBuffer<Rgba8Pixel>> {
-    let img = image::load_from_memory(compressed_data).ok()?.to_rgba8();
-    let (w, h) = img.dimensions();
+    let header = qoi::decode_header(compressed_data).ok()?;
+    if header.width > MAX_ICON_DIMENSION || header.height > MAX_ICON_DIMENSION {
+        return None;
+    }
+    let mut decoder = qoi::Decoder::new(compressed_data)
+        .ok()?
+        .with_channels(qoi::Channels::Rgba);
+    let pixels = decoder.decode_to_vec().ok()?;
+
+    Some(SharedPixelBuffer::clone_from_slice(
+        &pixels,
+        header.width,
+        header.height,
+    ))
+}
 
-    Some(SharedPixelBuffer::clone_from_slice(img.as_raw(), w, h))
+/// The logical size the theme draws icons at and the output scale, used to
+/// pick which of the daemon's renders to decode.
+#[derive(Clone, Copy)]
+struct IconTarget {
+    size: u16,
+    scale: u16,
+}
+
+/// Icons rendered by the daemon on request for records whose pre-rendered
+/// variants are all smaller than `target`, so they aren't drawn upscaled.
+/// Empty if the daemon can't be reached.
+fn fetch_missing_icons<'a>(
+    records: impl IntoIterator<Item = &'a EntryRecord>,
+    target: IconTarget,
+    socket_path: &Path,
+) -> HashMap<String, IconImage> {
+    let pixels = u32::from(target.size) * u32::from(target.scale.max(1));
+    let appids: Vec<&str> = records
+        .into_iter()
+        .filter(|record| {
+            record
+                .best_icon(target.size, target.scale)
+                .is_none_or(|icon| icon.width < pixels)
+        })
+        .map(|record| record.info.appid.as_str())
+        .collect();
+    if appids.is_empty() {
+        return HashMap::new();
+    }
+    daemon::fetch_icons(socket_path, &appids, target.size, target.scale).unwrap_or_else(|e| {
+        warn!(
+            "cannot fetch {} icons at {}x{}, scaling smaller ones: {}",
+            appids.len(),
+            target.size,
+            target.scale,
+            e
+        );
+        HashMap::new()
+    })
+}
+
+fn entry_from_record(
+    record: &EntryRecord,
+    target: IconTarget,
+    fetched: &HashMap<String, IconImage>,
+) -> Entry {
+    let icon = fetched
+        .get(&record.info.appid)
+        .or_else(|| record.best_icon(target.size, target.scale))
+        .and_then(|icon| decode_compressed_to_rgba(&icon.qoi));
+    if icon.is_none() {
+        warn!(
+            "invalid icon for {}, showing a placeholder",
+            record.info.appid
+        );
+    }
+    Entry {
+        info: record.info.clone(),
+        icon,
+    }
+}
+
+fn apply_diff(
+    entries: &mut Vec<Entry>,
+    diff: EntryDiff,
+    target: IconTarget,
+    fetched: &HashMap<String, IconImage>,
+) {
+    entries.retain(|e| !diff.removed.contains(&e.info.appid));
+
+    let changed: Vec<Entry> = diff
+        .changed
+        .par_iter()
+        .map(|record| entry_from_record(record, target, fetched))
+        .collect();
+    for entry in changed {
+        match entries
+            .iter_mut()
+            .find(|e| e.info.appid == entry.info.appid)
+        {
+            Some(slot) => *slot = entry,
+            None => entries.push(entry),
+        }
+    }
+
+    entries.par_extend(
+        diff.added
+            .par_iter()
+            .map(|record| entry_from_record(record, target, fetched)),
+    );
+}
+
+fn entries_for_query(
+    text: &str,
+    entries: &[Entry],
+    weights: &config::SearchConfig,
+    history: &HistoryMap,
+) -> Vec<Entry> {
+    let (categories, text) = split_category_filters(text);
+    let filtered: Vec<Entry>;
+    let entries = if categories.is_empty() {
+        entries
+    } else {
+        filtered = entries
+            .iter()
+            .filter(|e| in_categories(e, &categories))
+            .cloned()
+            .collect();
+        &filtered
+    };
+
+    if !text.is_empty() {
+        filter_and_sort_entries(&text, entries, weights, history)
+    } else {
+        sorted_entries_by_usage(entries, history, weights.half_life_days)
+    }
 }
 
 fn create_slint_items(normalized_entries: &[Entry], grid_config: config::GridConfig) -> AppItems {
@@ -155,11 +333,27 @@ fn create_slint_items(normalized_entries: &[Entry], grid_config: config::GridCon
 
     for entry in normalized_entries.iter() {
         model.push(AppItem {
-            app_name: entry.app_name.clone().into(),
-            app_id: entry.appid.clone().into(),
-            exec: entry.exec.clone().into(),
-            comment: entry.comment.clone().into(),
-            icon: Image::from_rgba8(entry.icon.clone()),
+            app_name: entry.info.app_name.clone().into(),
+            app_id: entry.info.appid.clone().into(),
+            exec: entry.info.exec.clone().into(),
+            comment: entry.info.comment.clone().into(),
+            // An empty image makes the UI show its placeholder icon.
+            icon: entry
+                .icon
+                .clone()
+                .map(Image::from_rgba8)
+                .unwrap_or_default(),
+            actions: ModelRc::new(VecModel::from(
+                entry
+                    .info
+                    .actions
+                    .iter()
+                    .map(|action| ActionItem {
+                        name: action.name.clone().into(),
+                        exec: action.exec.clone().into(),
+                    })
+                    .collect::<Vec<_>>(),
+            )),
         });
     }
 
@@ -176,6 +370,7 @@ fn create_slint_items(normalized_entries: &[Entry], grid_config: config::GridCon
                 exec: "".into(),
                 comment: "".into(),
                 icon: Image::from_rgba8(SharedPixelBuffer::new(1, 1)),
+                actions: ModelRc::default(),
             });
         }
         max_pages = pages as i32;
@@ -187,6 +382,59 @@ fn create_slint_items(normalized_entries: &[Entry], grid_config: config::GridCon
     }
 }
 
+/// Swaps the rows of the current model for `entries` filtered by the current
+/// query, so the list keeps its scroll position instead of being rebuilt.
+fn update_slint_items(
+    ui: &AppWindow,
+    entries: &[Entry],
+    grid_config: config::GridConfig,
+    weights: &config::SearchConfig,
+    history: &HistoryMap,
+) {
+    let sorted_entries = entries_for_query(&ui.get_text_input(), entries, weights, history);
+    let items = create_slint_items(&sorted_entries, grid_config);
+    let current = ui.get_appItems();
+
+    match current
+        .app_items
+        .as_any()
+        .downcast_ref::<VecModel<AppItem>>()
+    {
+        Some(model) => {
+            model.set_vec(items.app_items.iter().collect::<Vec<_>>());
+            ui.set_appItems(AppItems {
+                app_items: current.app_items.clone(),
+                max_pages: items.max_pages,
+            });
+        }
+        None => ui.set_appItems(items),
+    }
+
+    let row_count = ui.get_appItems().app_items.row_count() as i32;
+    if ui.get_selected_index() >= row_count {
+        ui.set_selected_index((row_count - 1).max(0));
+    }
+}
+
+/// Counts a launch of `appid` picked after typing `query` and refreshes the
+/// in-memory `history`. Failing to save it is reported but doesn't stop the
+/// launch.
+fn record_launch(history: &Mutex<HistoryMap>, appid: &str, query: &str, half_life_days: f64) {
+    let result = update_history(|history| {
+        increment_usage(history, appid, half_life_days);
+        record_query(history, appid, query);
+        true
+    });
+    match result {
+        Ok(updated) => *history.lock() = updated,
+        Err(e) => {
+            let msg = format!("Failed to save launch history: {}", e);
+            error!("{}", msg);
+            send_notification(&msg);
+        }
+    }
+}
+
 pub fn send_notification(message: &str) {
     let _ = Notification::new()
         .summary("Cosmic wanderer")
@@ -229,10 +477,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init_from_env(
         env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
     );
-    kill_all_if_multiple_instances();
-    unsafe {
-        std::env::set_var("QT_QPA_PLATFORM", "wayland");
-    }
 
     #[cfg(feature = "config_file")]
     let config = config::load_or_create_config().unwrap();
@@ -241,28 +485,70 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     debug!("[{:?}] Loaded config", start_time.elapsed());
 
+    // Files or URLs to open with the chosen application, unless this is a
+    // history command.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "history") {
+        if let Err(e) = cli::run(&args[1..], &config) {
+            eprintln!("cosmic-wanderer: {}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    kill_all_if_multiple_instances();
+    unsafe {
+        std::env::set_var("QT_QPA_PLATFORM", "wayland");
+    }
+
     let entries = Arc::new(Mutex::new(Vec::<Entry>::new()));
     let entries_for_thread = entries.clone();
+    // Read once here and replaced whenever we change it, rather than
+    // re-read for every keystroke.
+    let history = Arc::new(Mutex::new(HistoryMap::new()));
+    let history_for_thread = history.clone();
     let _ = set_xdg_app_id("cosmic-wanderer");
     let grid_config = config.theme.grid_config.clone();
+    let icon_size = config.theme.icon_size;
+    let (scale_tx, scale_rx) = mpsc::channel::<u16>();
+    let cache_path = PathBuf::from(&config.daemon.cache_path);
+    let cache_path_for_thread = cache_path.clone();
+    let socket_path = config.daemon.socket_path();
+    let socket_path_for_thread = socket_path.clone();
+    let half_life_days = config.search.half_life_days;
+    let history_config = config.history.clone();
 
     let fetch_handle = std::thread::spawn(move || {
         debug!("[{:?}] fetching items", start_time.elapsed());
-        let fetched = fetch_entries_from_file();
+        let fetched = fetch_entries_from_file(&cache_path_for_thread);
 
+        // Which render to decode depends on the output scale, which is only
+        // known once the window exists.
+        let target = IconTarget {
+            size: icon_size,
+            scale: scale_rx.recv().unwrap_or(1),
+        };
+        let fetched_icons = fetch_missing_icons(&fetched, target, &socket_path_for_thread);
         let decoded_entry: Vec<_> = fetched
             .par_iter()
-            .map(|entry_in| Entry {
-                app_name: entry_in.app_name.clone(),
-                appid: entry_in.appid.clone(),
-                exec: entry_in.exec.clone(),
-                comment: entry_in.comment.clone(),
-                icon: decode_compressed_to_rgba(&entry_in.icon_compressed).unwrap(),
-            })
+            .map(|record| entry_from_record(record, target, &fetched_icons))
             .collect();
 
-        let history = load_history();
-        let sorted = sorted_entries_by_usage(&decoded_entry, &history);
+        // An empty index means the cache couldn't be read, not that every
+        // app was uninstalled.
+        let history = if decoded_entry.is_empty() {
+            load_history()
+        } else {
+            update_history(|history| {
+                maintain_history(history, &decoded_entry, &history_config, half_life_days)
+            })
+            .unwrap_or_else(|e| {
+                warn!("Cannot update history: {}", e);
+                load_history()
+            })
+        };
+        let sorted = sorted_entries_by_usage(&decoded_entry, &history, half_life_days);
+        *history_for_thread.lock() = history;
         let mut locked = entries_for_thread.lock();
         *locked = sorted.clone();
         debug!("[{:?}] fetched items", start_time.elapsed());
@@ -272,24 +558,45 @@ fn main() -> Result<(), Box<dyn Error>> {
     debug!("[{:?}] creating ui", start_time.elapsed());
     let ui = AppWindow::new()?;
     debug!("[{:?}] UI created", start_time.elapsed());
+    let icon_target = IconTarget {
+        size: icon_size,
+        scale: ui.window().scale_factor().ceil().max(1.0) as u16,
+    };
+    let _ = scale_tx.send(icon_target.scale);
 
     let theme = theme_from_config(&config.theme);
     ui.window().set_maximized(config.theme.maximise);
     ui.set_theme(theme.clone());
 
     let grid_config_clone = grid_config.clone();
+    let search_config = config.search.clone();
     let ui_weak = ui.as_weak();
+    let entries_for_updates = entries.clone();
+    let entries_for_click = entries.clone();
+    let entries_for_action = entries.clone();
+    let entries_for_forget = entries.clone();
+    let history_for_text = history.clone();
+    let history_for_click = history.clone();
+    let history_for_action = history.clone();
+    let history_for_forget = history.clone();
+    let history_for_updates = history.clone();
+    let targets: Arc<Vec<String>> = Arc::new(args);
+    let targets_for_click = targets.clone();
+    let launch_config = config.launch.clone();
+    let launch_config_for_click = launch_config.clone();
+    let socket_path_for_click = socket_path.clone();
+    let socket_path_for_action = socket_path.clone();
 
     let ui_weak_clone_text = ui.as_weak();
     ui.on_text_entered(move |text| {
         debug!("[{:?}] User typed: '{}'", start_time.elapsed(), text);
         let locked_entries = entries.lock();
-        let sorted_entries = if !text.is_empty() {
-            filter_and_sort_entries(&text, &locked_entries)
-        } else {
-            let history = load_history();
-            sorted_entries_by_usage(&locked_entries, &history)
-        };
+        let sorted_entries = entries_for_query(
+            &text,
+            &locked_entries,
+            &search_config,
+            &history_for_text.lock(),
+        );
 
         let vec_model = create_slint_items(&sorted_entries, grid_config_clone.clone());
 
@@ -314,54 +621,91 @@ fn main() -> Result<(), Box<dyn Error>> {
                     debug!("[{:?}] Empty exec string, skipping", start_time.elapsed());
                     return;
                 }
-                let mut history = load_history();
-
-                increment_usage(&mut history, &entry.app_id);
-                save_history(&history);
-                drop(history);
-
-                let command_string = entry
-                    .exec
-                    .replace("%U", "")
-                    .replace("%F", "")
-                    .replace("%u", "")
-                    .replace("%f", "");
-
-                let command: Vec<String> = Shlex::new(&command_string).collect();
-
-                if let Some((cmd, args)) = command.split_first() {
-                    let mut command = Command::new(cmd);
-                    command
-                        .args(args)
-                        .stdin(Stdio::null())
-                        .stdout(Stdio::null())
-                        .stderr(Stdio::null());
-
-                    unsafe {
-                        command.pre_exec(|| {
-                            libc::setsid();
-                            Ok(())
-                        });
-                    }
-
-                    debug!(
-                        "[{:?}] Launching command: {:?}",
-                        start_time.elapsed(),
-                        command
-                    );
-
-                    if let Err(e) = command.spawn() {
-                        let msg = format!("Failed to spawn detached process: {}", e);
-                        error!("[{:?}] {}", start_time.elapsed(), msg);
-                        send_notification(&msg);
-                    }
-                }
+                let query = split_category_filters(&ui.get_text_input()).1;
+                record_launch(&history_for_click, &entry.app_id, &query, half_life_days);
+
+                launch::launch_for(
+                    &entry.exec,
+                    &entry.app_id,
+                    &entries_for_click.lock(),
+                    &targets_for_click,
+                    &launch_config_for_click,
+                    &socket_path_for_click,
+                    start_time,
+                );
                 drop(entries);
                 ui.hide().unwrap();
             }
         }
     });
 
+    let ui_weak_clone_forget = ui.as_weak();
+    let grid_config_forget = grid_config.clone();
+    let search_config_forget = config.search.clone();
+    ui.on_forget_item(move |idx| {
+        let Some(ui) = ui_weak_clone_forget.upgrade() else {
+            return;
+        };
+        let Some(entry) = ui.get_appItems().app_items.row_data(idx as usize) else {
+            return;
+        };
+        debug!(
+            "[{:?}] Forgetting history of {}",
+            start_time.elapsed(),
+            entry.app_id
+        );
+        match update_history(|history| forget(history, &entry.app_id)) {
+            Ok(updated) => *history_for_forget.lock() = updated,
+            Err(e) => {
+                let msg = format!("Failed to forget {}: {}", entry.app_name, e);
+                error!("[{:?}] {}", start_time.elapsed(), msg);
+                send_notification(&msg);
+                return;
+            }
+        }
+        update_slint_items(
+            &ui,
+            &entries_for_forget.lock(),
+            grid_config_forget.clone(),
+            &search_config_forget,
+            &history_for_forget.lock(),
+        );
+    });
+
+    let ui_weak_clone_action = ui.as_weak();
+    ui.on_action_clicked(move |idx, action_idx| {
+        debug!(
+            "[{:?}] Action clicked: item {} action {}",
+            start_time.elapsed(),
+            idx,
+            action_idx
+        );
+
+        if let Some(ui) = ui_weak_clone_action.upgrade() {
+            let entries = ui.get_appItems();
+            let Some(entry) = entries.app_items.row_data(idx as usize) else {
+                return;
+            };
+            let Some(action) = entry.actions.row_data(action_idx as usize) else {
+                return;
+            };
+            let query = split_category_filters(&ui.get_text_input()).1;
+            record_launch(&history_for_action, &entry.app_id, &query, half_life_days);
+
+            launch::launch_for(
+                &action.exec,
+                &entry.app_id,
+                &entries_for_action.lock(),
+                &targets,
+                &launch_config,
+                &socket_path_for_action,
+                start_time,
+            );
+            ui.set_action_index(-1);
+            ui.hide().unwrap();
+        }
+    });
+
     let ui_for_focus_thread = ui.as_weak();
     thread::spawn(move || {
         loop {
@@ -396,6 +740,57 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.invoke_focusText();
     let items = fetch_handle.join().expect("Fetch thread panicked");
 
+    let ui_for_updates = ui.as_weak();
+    let grid_config_updates = grid_config.clone();
+    let search_config_updates = config.search.clone();
+    daemon::spawn_subscriber(socket_path.clone(), move |update| {
+        match update {
+            Update::Resync => {
+                let fetched = fetch_entries_from_file(&cache_path);
+                let fetched_icons = fetch_missing_icons(&fetched, icon_target, &socket_path);
+                let decoded = fetched
+                    .par_iter()
+                    .map(|record| entry_from_record(record, icon_target, &fetched_icons))
+                    .collect();
+                *entries_for_updates.lock() = decoded;
+            }
+            Update::Diff(diff) => {
+                let fetched_icons = fetch_missing_icons(
+                    diff.changed.iter().chain(&diff.added),
+                    icon_target,
+                    &socket_path,
+                );
+                apply_diff(
+                    &mut entries_for_updates.lock(),
+                    diff,
+                    icon_target,
+                    &fetched_icons,
+                );
+            }
+        }
+        debug!("[{:?}] applied entry update", start_time.elapsed());
+
+        let entries = entries_for_updates.clone();
+        let history = history_for_updates.clone();
+        let ui_weak = ui_for_updates.clone();
+        let grid_config = grid_config_updates.clone();
+        let search_config = search_config_updates.clone();
+        slint::invoke_from_event_loop(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                update_slint_items(
+                    &ui,
+                    &entries.lock(),
+                    grid_config,
+                    &search_config,
+                    &history.lock(),
+                );
+            }
+        })
+        .unwrap_or_else(|e| {
+            error!("[{:?}] Invoke failed update: {}", start_time.elapsed(), e);
+        });
+    });
+
     slint::invoke_from_event_loop(move || {
         if let Some(ui) = ui_weak.upgrade() {
             debug!("[{:?}] creating", start_time.elapsed());